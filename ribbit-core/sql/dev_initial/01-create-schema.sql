@@ -1,9 +0,0 @@
----- Base app schema
-
-CREATE TABLE "story" (
-    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
-
-    story VARCHAR(128) NOT NULL
-);
-
-
@@ -1,3 +1,6 @@
--- demo story
-INSERT INTO "story" (story) VALUES ('This is a demo story.');
+-- root user (at id = 0)
+INSERT INTO "user" (id, username) VALUES (0, 'root');
+
+-- User demo1 (api key set by dev_db)
+INSERT INTO "user" (username) VALUES ('demo1');
 
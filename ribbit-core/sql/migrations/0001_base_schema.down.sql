@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS "embedding_cache";
+DROP TABLE IF EXISTS "story_outbox";
+DROP TABLE IF EXISTS "story";
+DROP TABLE IF EXISTS "user";
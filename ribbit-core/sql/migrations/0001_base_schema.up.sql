@@ -0,0 +1,53 @@
+---- Base app schema
+
+-- User
+CREATE TABLE "user" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+
+    username VARCHAR(128) NOT NULL UNIQUE,
+
+    -- sha256 hex of the api key, never the key itself.
+    api_key_hash VARCHAR(64) UNIQUE
+);
+
+CREATE TABLE "story" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+
+    owner_id BIGINT NOT NULL REFERENCES "user" (id),
+
+    story TEXT NOT NULL,
+    status VARCHAR(32) NOT NULL DEFAULT 'open',
+    tags TEXT[] NOT NULL DEFAULT '{}',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX story_owner_id_idx ON "story" (owner_id);
+
+-- Vector store changes, written in the same transaction as the story change
+-- and applied by the outbox worker.
+CREATE TABLE "story_outbox" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+
+    story_id BIGINT NOT NULL,
+    owner_id BIGINT NOT NULL,
+    op VARCHAR(16) NOT NULL, -- 'upsert' or 'delete'
+
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX story_outbox_next_attempt_at_idx ON "story_outbox" (next_attempt_at);
+
+-- Embeddings by (model, sha256 hex of the text), see `CachedEmbedder`.
+CREATE TABLE "embedding_cache" (
+    model VARCHAR(128) NOT NULL,
+    text_hash VARCHAR(64) NOT NULL,
+
+    embedding REAL[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (model, text_hash)
+);
@@ -0,0 +1,5 @@
+DROP INDEX story_outbox_story_id_idx;
+
+ALTER TABLE "story_outbox"
+    DROP COLUMN dead_at,
+    DROP COLUMN claimed_until;
@@ -0,0 +1,9 @@
+---- Outbox claims and give-up state, see `OutboxBmc::process_pending`.
+
+ALTER TABLE "story_outbox"
+    -- Set while a worker applies the entries of the story.
+    ADD COLUMN claimed_until TIMESTAMPTZ,
+    -- Set once the entry failed `MAX_ATTEMPTS` times, it is then left for inspection.
+    ADD COLUMN dead_at TIMESTAMPTZ;
+
+CREATE INDEX story_outbox_story_id_idx ON "story_outbox" (story_id);
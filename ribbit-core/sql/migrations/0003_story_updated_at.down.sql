@@ -0,0 +1,2 @@
+ALTER TABLE "story"
+    DROP COLUMN updated_at;
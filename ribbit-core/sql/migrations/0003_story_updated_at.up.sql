@@ -0,0 +1,4 @@
+---- Last change of a story, see the catch up of `reindex`.
+
+ALTER TABLE "story"
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
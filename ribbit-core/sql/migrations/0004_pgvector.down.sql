@@ -0,0 +1,2 @@
+-- The `vector` extension is left, other schemas may use it.
+DROP TABLE vec_collection;
@@ -0,0 +1,17 @@
+---- pgvector backend, see `PgVecStore`. The `vec_{name}` tables follow the collections config.
+
+-- Optional, the Qdrant and memory backends don't need it.
+-- NOTE: `CREATE EXTENSION` needs a privileged user.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector') THEN
+        CREATE EXTENSION IF NOT EXISTS vector;
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS vec_collection (
+    name VARCHAR(64) PRIMARY KEY,
+    dim BIGINT NOT NULL,
+    distance VARCHAR(16) NOT NULL
+);
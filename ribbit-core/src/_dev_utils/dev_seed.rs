@@ -0,0 +1,204 @@
+//! Dev seed: the demo tasks of the fixture files.
+//!     - a `.json`, `.yaml` or `.yml` file holds a list of tasks, the files are read by name
+//!     - each task goes through `TaskBmc::create`, then the outbox is drained,
+//!       so the stories are searchable once seeded
+//!
+//! NOTE: The tasks are embedded with the embedder of `mm`, `"embedder": "hashing"` or `"local"`
+//!       seeds without network.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::ctx::Ctx;
+use crate::model::embedder::Embedder;
+use crate::model::outbox::OutboxBmc;
+use crate::model::task::{TaskBmc, TaskForCreate};
+use crate::model::user::UserBmc;
+use crate::model::{ModelManager, VectorBackend};
+
+pub const FIXTURES_DIR: &str = "fixtures/tasks";
+
+#[derive(Deserialize)]
+pub struct TaskFixture {
+    /// Username of the owner, `root` when not given.
+    pub owner: Option<String>,
+    #[serde(flatten)]
+    pub task: TaskForCreate,
+}
+
+/// The fixtures of `dir`, by file name then position. The other files are ignored.
+pub fn load_fixtures(dir: impl AsRef<Path>) -> Result<Vec<TaskFixture>, Box<dyn Error>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    paths.sort();
+
+    let mut fixtures = Vec::new();
+    for path in paths {
+        let parse_err = |ex: &dyn Error| format!("fixture {}: {ex}", path.display());
+        let file_fixtures: Vec<TaskFixture> =
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|ex| parse_err(&ex))?,
+                Some("yaml" | "yml") => serde_yaml::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|ex| parse_err(&ex))?,
+                _ => continue,
+            };
+        fixtures.extend(file_fixtures);
+    }
+
+    Ok(fixtures)
+}
+
+/// Create the fixture tasks of `dir` and embed them. Returns the task ids.
+pub async fn seed_tasks(
+    mm: ModelManager<impl Embedder, impl VectorBackend>,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    let fixtures = load_fixtures(dir)?;
+
+    let mut ctxs: HashMap<String, Ctx> = HashMap::new();
+    let mut ids = Vec::with_capacity(fixtures.len());
+    for TaskFixture { owner, task } in fixtures {
+        let owner = owner.unwrap_or_else(|| "root".to_string());
+        let ctx = match ctxs.get(&owner) {
+            Some(ctx) => ctx.clone(),
+            None => {
+                let ctx = owner_ctx(mm.clone(), &owner).await?;
+                ctxs.insert(owner, ctx.clone());
+                ctx
+            }
+        };
+        ids.push(TaskBmc::create(ctx, mm.clone(), task).await?);
+    }
+
+    // -- Embed now, not on the next poll of the worker.
+    OutboxBmc::process_all(mm).await?;
+    info!(
+        "{:<12} - seed_tasks() - {} tasks",
+        "FOR-DEV-ONLY",
+        ids.len()
+    );
+
+    Ok(ids)
+}
+
+async fn owner_ctx(
+    mm: ModelManager<impl Embedder, impl VectorBackend>,
+    username: &str,
+) -> Result<Ctx, Box<dyn Error>> {
+    if username == "root" {
+        return Ok(Ctx::root_ctx());
+    }
+    let user = UserBmc::first_by_username(Ctx::root_ctx(), mm, username)
+        .await?
+        .ok_or_else(|| format!("fixture owner not found: {username}"))?;
+
+    Ok(Ctx::new(user.id, user.username)?)
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::_dev_utils;
+    use crate::model::embedder::HashingEmbedder;
+    use crate::model::store::{PointFilter, SearchOptions};
+    use crate::model::MemVecStore;
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    /// Temp dir with the `files`.
+    fn temp_dir(files: &[(&str, &str)]) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("ribbit_seed_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir)?;
+        for (name, content) in files {
+            fs::write(dir.join(name), content)?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_load_fixtures_ok() -> Result<()> {
+        let dir = temp_dir(&[
+            (
+                "b.yaml",
+                "- story: Sort the lily pads\n  owner: demo1\n  tags: [pond]\n",
+            ),
+            (
+                "a.json",
+                r#"[{"story": "Count the frogs", "status": "done"}]"#,
+            ),
+            ("README.md", "not a fixture"),
+        ])?;
+        let fixtures = load_fixtures(&dir).map_err(|ex| anyhow::anyhow!("{ex}"))?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(fixtures.len(), 2);
+        assert_eq!(fixtures[0].task.story, "Count the frogs");
+        assert_eq!(fixtures[0].task.status.as_deref(), Some("done"));
+        assert!(fixtures[0].owner.is_none());
+        assert_eq!(fixtures[1].owner.as_deref(), Some("demo1"));
+        assert_eq!(fixtures[1].task.tags, vec!["pond".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_fixtures_err() -> Result<()> {
+        let dir = temp_dir(&[("a.json", r#"[{"status": "open"}]"#)])?;
+        let res = load_fixtures(&dir);
+        fs::remove_dir_all(&dir)?;
+
+        let err = res.err().map(|ex| ex.to_string()).unwrap_or_default();
+        assert!(err.contains("a.json"), "{err}");
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_seed_tasks_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let dir = temp_dir(&[(
+            "tasks.json",
+            r#"[
+                {"story": "Seed the tadpole nursery", "owner": "demo1"},
+                {"story": "Seed the heron watch rota"}
+            ]"#,
+        )])?;
+        let res = seed_tasks(mm.clone(), &dir).await;
+        fs::remove_dir_all(&dir)?;
+        let ids = res.map_err(|ex| anyhow::anyhow!("{ex}"))?;
+        assert_eq!(ids.len(), 2);
+
+        // -- Searchable by their owner only.
+        let demo1 = owner_ctx(mm.clone(), "demo1")
+            .await
+            .map_err(|ex| anyhow::anyhow!("{ex}"))?;
+        for (ctx, id) in [(demo1, ids[0]), (Ctx::root_ctx(), ids[1])] {
+            let hits = TaskBmc::search(
+                ctx.clone(),
+                mm.clone(),
+                "seed",
+                &PointFilter::default(),
+                &SearchOptions::new(10),
+            )
+            .await?;
+            assert!(hits.iter().any(|hit| hit.task.id == id));
+            assert!(!hits
+                .iter()
+                .any(|hit| ids.contains(&hit.task.id) && hit.task.id != id));
+            TaskBmc::delete(ctx, mm.clone(), id).await?;
+        }
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -1,9 +1,13 @@
 use tokio::sync::OnceCell;
 use tracing::info;
 
-use crate::{model::VecStore, config};
+use crate::model::embedder::Embedder;
+use crate::model::{ModelManager, VectorBackend};
 
 mod dev_db;
+mod dev_seed;
+
+pub use dev_db::DEMO_API_KEY;
 
 pub async fn init_dev() {
     static INIT: OnceCell<()> = OnceCell::const_new();
@@ -14,15 +18,21 @@ pub async fn init_dev() {
         dev_db::init_dev_db().await.unwrap();
     })
     .await;
-
-    let _ = VecStore::from_config()
-        .await
-        .unwrap()
-        .reset_collection(config().qdrant.collections.first().unwrap())
-        // .delete_collection(VS_COLLECTION_NAME)
-        .await;
 }
 
-pub async fn seed_tasks() {
-    todo!()
+/// Seed the demo tasks of `fixtures/tasks`, once per process. Call after `init_dev`.
+/// A failed seed is retried by the next call.
+pub async fn seed_tasks(
+    mm: ModelManager<impl Embedder, impl VectorBackend>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    static SEED: OnceCell<()> = OnceCell::const_new();
+
+    SEED.get_or_try_init(|| async {
+        info!("{:<12} - seed_tasks()", "FOR-DEV-ONLY");
+
+        dev_seed::seed_tasks(mm, dev_seed::FIXTURES_DIR).await?;
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })
+    .await?;
+    Ok(())
 }
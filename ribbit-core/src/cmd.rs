@@ -0,0 +1,135 @@
+use crate::error::{Error, Result};
+
+/// Subcommand of the binary, `serve` when none is given.
+///
+/// Usage: `ribbit-core [serve | reconcile [--repair] | reindex | migrate [up | down [N] | status]]`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Reconcile { repair: bool },
+    Reindex,
+    Migrate(MigrateCmd),
+}
+
+/// `migrate` subcommand, `up` when none is given. `down` reverts 1 migration by default.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrateCmd {
+    Up,
+    Down { steps: usize },
+    Status,
+}
+
+impl Command {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let cmd = match args.next().as_deref() {
+            None | Some("serve") => Command::Serve,
+            Some("reconcile") => Command::Reconcile { repair: false },
+            Some("reindex") => Command::Reindex,
+            Some("migrate") => return MigrateCmd::from_args(args).map(Command::Migrate),
+            Some(other) => return Err(Error::CmdUnknown(other.to_string())),
+        };
+
+        args.try_fold(cmd, |cmd, flag| match (cmd, flag.as_str()) {
+            (Command::Reconcile { .. }, "--repair") => Ok(Command::Reconcile { repair: true }),
+            _ => Err(Error::CmdUnknownFlag(flag)),
+        })
+    }
+}
+
+impl MigrateCmd {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let cmd = match args.next().as_deref() {
+            None | Some("up") => MigrateCmd::Up,
+            Some("status") => MigrateCmd::Status,
+            Some("down") => {
+                let steps = match args.next() {
+                    None => 1,
+                    Some(steps) => steps.parse().map_err(|_| Error::CmdUnknownFlag(steps))?,
+                };
+                MigrateCmd::Down { steps }
+            }
+            Some(other) => return Err(Error::CmdUnknown(format!("migrate {other}"))),
+        };
+
+        match args.next() {
+            None => Ok(cmd),
+            Some(flag) => Err(Error::CmdUnknownFlag(flag)),
+        }
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_from_args_ok() -> Result<()> {
+        assert_eq!(Command::from_args(args(&[]))?, Command::Serve);
+        assert_eq!(Command::from_args(args(&["serve"]))?, Command::Serve);
+        assert_eq!(
+            Command::from_args(args(&["reconcile"]))?,
+            Command::Reconcile { repair: false }
+        );
+        assert_eq!(
+            Command::from_args(args(&["reconcile", "--repair"]))?,
+            Command::Reconcile { repair: true }
+        );
+        assert_eq!(Command::from_args(args(&["reindex"]))?, Command::Reindex);
+        assert_eq!(
+            Command::from_args(args(&["migrate"]))?,
+            Command::Migrate(MigrateCmd::Up)
+        );
+        assert_eq!(
+            Command::from_args(args(&["migrate", "down"]))?,
+            Command::Migrate(MigrateCmd::Down { steps: 1 })
+        );
+        assert_eq!(
+            Command::from_args(args(&["migrate", "down", "3"]))?,
+            Command::Migrate(MigrateCmd::Down { steps: 3 })
+        );
+        assert_eq!(
+            Command::from_args(args(&["migrate", "status"]))?,
+            Command::Migrate(MigrateCmd::Status)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_args_err() {
+        assert!(matches!(
+            Command::from_args(args(&["foo"])),
+            Err(Error::CmdUnknown(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["serve", "--repair"])),
+            Err(Error::CmdUnknownFlag(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["reindex", "--repair"])),
+            Err(Error::CmdUnknownFlag(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["migrate", "sideways"])),
+            Err(Error::CmdUnknown(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["migrate", "down", "x"])),
+            Err(Error::CmdUnknownFlag(_))
+        ));
+        assert!(matches!(
+            Command::from_args(args(&["migrate", "up", "1"])),
+            Err(Error::CmdUnknownFlag(_))
+        ));
+    }
+}
+// endregion: --- Test
@@ -16,11 +16,58 @@ pub struct Config {
     pub database: Database,
     pub qdrant: Qdrant,
     pub openai_embedder: OpenAIEmbedder,
+    pub hashing_embedder: Option<HashingEmbedder>,
+    pub local_embedder: Option<LocalEmbedder>,
+    #[serde(default)]
+    pub embedding_cache: EmbeddingCache,
+    #[serde(default)]
+    pub chunking: Chunking,
+    #[serde(default)]
+    pub embedder: EmbedderKind,
+    #[serde(default)]
+    pub vector_backend: VectorBackendKind,
+    /// Recreate app_db on `serve` (dev only), see `_dev_utils::init_dev`.
+    #[serde(default)]
+    pub dev_reset_db: bool,
+    /// Seed the demo tasks on `serve` (dev only), see `_dev_utils::seed_tasks`.
+    #[serde(default)]
+    pub dev_seed: bool,
+}
+
+/// Which `Embedder` computes the embeddings.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedderKind {
+    #[default]
+    OpenAI,
+    Hashing,
+    /// Needs the `local-embedder` feature.
+    Local,
+}
+
+/// Where the embeddings are stored. The collections of `qdrant` apply to every backend.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorBackendKind {
+    #[default]
+    Qdrant,
+    PgVector,
+    Memory,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Database {
     pub db_url: String,
+    /// `{version}_{name}.up.sql` / `.down.sql` files, see `model::migrate`.
+    #[serde(default = "default_migrations_dir")]
+    pub migrations_dir: String,
+    /// Apply the pending migrations before serving.
+    #[serde(default)]
+    pub migrate_on_start: bool,
+}
+
+fn default_migrations_dir() -> String {
+    "sql/migrations".to_string()
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,15 +76,186 @@ pub struct Qdrant {
     pub collections: Vec<QdrantCollection>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Qdrant {
+    /// Only a `dev` collection may be reset on start.
+    pub fn validate(&self) -> Result<()> {
+        for clct in &self.collections {
+            if clct.reset_on_start && clct.role != CollectionRole::Dev {
+                return Err(Error::ConfigInvalid(format!(
+                    "collection {}: reset_on_start needs the dev role, not {:?}",
+                    clct.name, clct.role
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The vectors of a `prod` collection are never deleted by the app.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionRole {
+    Dev,
+    Test,
+    #[default]
+    Prod,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct QdrantCollection {
     pub name: String,
     pub dim: u64,
     pub distance: String,
+    #[serde(default)]
+    pub role: CollectionRole,
+    /// Recreate the collection empty on start, see `VectorBackend::init_collections`.
+    #[serde(default)]
+    pub reset_on_start: bool,
 }
+/// Any OpenAI compatible server (Azure, vLLM, Ollama, ...) through `api_base`.
 #[derive(Debug, Deserialize)]
 pub struct OpenAIEmbedder {
     pub model: String,
+    /// Defaults to `https://api.openai.com/v1`.
+    pub api_base: Option<String>,
+    /// Env var holding the api key.
+    #[serde(default = "default_openai_api_key_env")]
+    pub api_key_env: String,
+    pub org_id: Option<String>,
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub retry: EmbedderRetry,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    #[serde(default)]
+    pub batch: EmbedderBatch,
+}
+
+fn default_openai_api_key_env() -> String {
+    "OPENAI_API_KEY".to_string()
+}
+
+/// Retries of the rate limited (429), 5xx and network failures.
+#[derive(Debug, Deserialize)]
+pub struct EmbedderRetry {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+/// Limits of one embeddings request, the token counts are estimates (~4 chars per token).
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbedderBatch {
+    pub max_inputs: usize,
+    pub max_tokens: usize,
+    pub max_input_tokens: usize,
+    /// Requests in flight for one `embeds` call.
+    pub concurrency: usize,
+    #[serde(default)]
+    pub overflow: InputOverflow,
+}
+
+impl EmbedderBatch {
+    /// A limit of 0 would stall the batching or cut every input to nothing.
+    pub fn validate(&self) -> Result<()> {
+        let limits = [
+            ("max_inputs", self.max_inputs),
+            ("max_tokens", self.max_tokens),
+            ("max_input_tokens", self.max_input_tokens),
+            ("concurrency", self.concurrency),
+        ];
+        match limits.iter().find(|(_, limit)| *limit == 0) {
+            Some((name, _)) => Err(Error::ConfigInvalid(format!(
+                "openai_embedder.batch.{name} must be above 0"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for EmbedderBatch {
+    fn default() -> Self {
+        EmbedderBatch {
+            max_inputs: 2048,
+            max_tokens: 100_000,
+            max_input_tokens: 8000,
+            concurrency: 4,
+            overflow: InputOverflow::default(),
+        }
+    }
+}
+
+/// What to do with an input above `max_input_tokens`.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputOverflow {
+    #[default]
+    Truncate,
+    /// Embed the chunks and average them.
+    Chunk,
+    Reject,
+}
+
+impl Default for EmbedderRetry {
+    fn default() -> Self {
+        EmbedderRetry {
+            max_retries: 5,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+/// `dim` must match the dim of the collections.
+#[derive(Debug, Deserialize)]
+pub struct HashingEmbedder {
+    pub dim: u64,
+}
+
+/// `dim` must match both the output of the model and the dim of the collections.
+#[derive(Debug, Deserialize)]
+pub struct LocalEmbedder {
+    pub model_path: String,
+    pub dim: u64,
+    #[serde(default = "default_local_batch_size")]
+    pub batch_size: usize,
+}
+
+fn default_local_batch_size() -> usize {
+    32
+}
+
+/// In process LRU of `capacity` embeddings, backed by the `embedding_cache` table if `persist`.
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingCache {
+    pub capacity: usize,
+    #[serde(default)]
+    pub persist: bool,
+}
+
+impl Default for EmbeddingCache {
+    fn default() -> Self {
+        EmbeddingCache {
+            capacity: 1024,
+            persist: false,
+        }
+    }
+}
+
+/// Token budget of the story chunks, each chunk is one point of the task.
+#[derive(Debug, Deserialize)]
+pub struct Chunking {
+    pub max_tokens: usize,
+    pub overlap_tokens: usize,
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Chunking {
+            max_tokens: 256,
+            overlap_tokens: 32,
+        }
+    }
 }
 
 impl Config {
@@ -48,6 +266,8 @@ impl Config {
             .map_err(|_| Error::ConfigReadConfigFile(conf_path.clone()))?;
         let conf: Config =
             serde_json::from_str(&conf_str).map_err(|_| Error::ConfigParseConfigFile(conf_path))?;
+        conf.qdrant.validate()?;
+        conf.openai_embedder.batch.validate()?;
         Ok(conf)
     }
 }
@@ -64,5 +284,43 @@ mod tests {
         println!("{:?}", conf);
         Ok(())
     }
+
+    #[test]
+    fn test_qdrant_validate_err_reset_not_dev() -> Result<()> {
+        let clct = |role, reset_on_start| QdrantCollection {
+            name: "foo".to_string(),
+            dim: 2,
+            distance: "Cosine".to_string(),
+            role,
+            reset_on_start,
+        };
+        let qdrant = |collections| Qdrant {
+            url: "http://localhost:6334".to_string(),
+            collections,
+        };
+        qdrant(vec![
+            clct(CollectionRole::Dev, true),
+            clct(CollectionRole::Prod, false),
+        ])
+        .validate()?;
+        for role in [CollectionRole::Test, CollectionRole::Prod] {
+            assert!(matches!(
+                qdrant(vec![clct(role, true)]).validate(),
+                Err(Error::ConfigInvalid(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_embedder_batch_validate_err_zero() -> Result<()> {
+        EmbedderBatch::default().validate()?;
+        let batch = EmbedderBatch {
+            max_input_tokens: 0,
+            ..Default::default()
+        };
+        assert!(matches!(batch.validate(), Err(Error::ConfigInvalid(_))));
+        Ok(())
+    }
 }
 // endregion: --- Test
@@ -0,0 +1,26 @@
+use sha2::{Digest, Sha256};
+
+/// API keys are random and long, so a plain sha256 is enough to keep them out of the db.
+pub fn hash_api_key(api_key: &str) -> String {
+    sha256_hex(api_key)
+}
+
+pub fn sha256_hex(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn test_hash_api_key_ok() {
+        assert_eq!(
+            hash_api_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
+// endregion: --- Test
@@ -9,6 +9,7 @@ pub use self::error::{Error, Result};
 #[derive(Clone, Debug)]
 pub struct Ctx {
     pub user_id: i64,
+    #[allow(dead_code)]
     pub user_name: String,
     //TODO add more fields, system_message
 }
@@ -1,13 +1,26 @@
+use crate::model;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
+    // -- Modules
+    Model(model::Error),
+
+    // cmd
+    CmdUnknown(String),
+    CmdUnknownFlag(String),
+
     // config
     ConfigMissingEnv(&'static str),
     ConfigParseInt { var_name: String },
     ConfigParseConfigFile(String),
     ConfigReadConfigFile(String),
+    ConfigInvalid(String),
     DotEnvNotFound,
+
+    // dev
+    DevSeed(String),
 }
 
 // region:    --- Error Boilerplate
@@ -18,3 +31,11 @@ impl core::fmt::Display for Error {
 }
 impl std::error::Error for Error {}
 // endregion: --- Error Boilerplate
+
+// region:   --- error from
+impl From<model::Error> for Error {
+    fn from(err: model::Error) -> Self {
+        Self::Model(err)
+    }
+}
+// endregion: --- error from
@@ -1,15 +1,23 @@
+use std::env;
 use std::net::SocketAddr;
 
+use crate::cmd::{Command, MigrateCmd};
 use crate::error::{Error, Result};
-use axum::{routing::get, Router};
+use crate::model::embedder::{AnyEmbedder, CachedEmbedder};
+use crate::model::store::new_db_pool;
+use crate::model::ModelManager;
+use axum::{middleware, routing::get, Router};
 use dotenvy::dotenv;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
+mod cmd;
 mod config;
+mod crypt;
 mod ctx;
 mod error;
 mod model;
+mod web;
 
 pub use config::config;
 pub mod _dev_utils;
@@ -22,9 +30,70 @@ async fn main() -> Result<()> {
         .without_time()
         .with_target(false)
         .init();
-    _dev_utils::init_dev().await;
 
-    let routes_all = Router::new().route("/hello", get(hello));
+    match Command::from_args(env::args().skip(1))? {
+        Command::Serve => serve().await,
+        Command::Reconcile { repair } => {
+            let mm = ModelManager::<CachedEmbedder<AnyEmbedder>>::from_config().await?;
+            let report = model::reconcile::reconcile(mm, repair).await?;
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            Ok(())
+        }
+        Command::Reindex => {
+            let mm = ModelManager::<CachedEmbedder<AnyEmbedder>>::from_config().await?;
+            let report = model::reindex::reindex(mm.clone()).await?;
+            info!(
+                "{:<12} - embedding cache {:?}",
+                "REINDEX",
+                mm.embedder.stats()
+            );
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            Ok(())
+        }
+        Command::Migrate(cmd) => {
+            let db = new_db_pool().await.map_err(model::Error::from)?;
+            let dir = &config().database.migrations_dir;
+            let report = match cmd {
+                MigrateCmd::Up => serde_json::json!(model::migrate::up(&db, dir).await?),
+                MigrateCmd::Down { steps } => {
+                    serde_json::json!(model::migrate::down(&db, dir, steps).await?)
+                }
+                MigrateCmd::Status => serde_json::json!(model::migrate::status(&db, dir).await?),
+            };
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            Ok(())
+        }
+    }
+}
+
+async fn serve() -> Result<()> {
+    if config().dev_reset_db {
+        _dev_utils::init_dev().await;
+    }
+
+    // -- Bring the schema up to date, when not done by the deploy.
+    if config().database.migrate_on_start {
+        let db = new_db_pool().await.map_err(model::Error::from)?;
+        model::migrate::up(&db, &config().database.migrations_dir).await?;
+    }
+
+    // -- Initialize ModelManager.
+    let mm = ModelManager::<CachedEmbedder<AnyEmbedder>>::from_config().await?;
+
+    // -- Demo tasks, embedded by the configured embedder.
+    if config().dev_seed {
+        _dev_utils::seed_tasks(mm.clone())
+            .await
+            .map_err(|ex| Error::DevSeed(ex.to_string()))?;
+    }
+
+    // -- Keep the vector store in sync with the story table.
+    model::outbox::spawn_outbox_worker(mm.clone());
+
+    let routes_all = Router::new()
+        .route("/hello", get(hello))
+        .nest("/api", web::routes_tasks::routes(mm))
+        .layer(middleware::map_response(web::mw_res_map::mw_response_map));
 
     // region:    --- Start Server
     let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
@@ -0,0 +1,160 @@
+use crate::model::embedder::estimate_tokens;
+
+///
+/// Chunker: split a long story into overlapping chunks for the embedding.
+///     - the units are the sentences and lines of the story, a unit above the budget is cut
+///     - units are packed up to `max_tokens`, the next chunk repeats up to `overlap_tokens`
+///       of the previous one
+///
+/// Offsets and lengths are in chars, as the postgres `substr`.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: usize,
+    pub len: usize,
+    pub text: String,
+}
+
+/// Never empty, a text without any unit is a single (empty) chunk.
+pub fn chunk_text(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<Chunk> {
+    let chars: Vec<char> = text.chars().collect();
+    let units = split_units(&chars, max_tokens.max(1) * 4);
+    if units.is_empty() {
+        return vec![Chunk {
+            offset: 0,
+            len: chars.len(),
+            text: text.to_string(),
+        }];
+    }
+    let tokens: Vec<usize> = units
+        .iter()
+        .map(|(start, end)| {
+            estimate_tokens(&chars[*start..*end].iter().collect::<String>()) as usize
+        })
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    loop {
+        // -- Pack the units, at least one.
+        let mut last = first;
+        let mut chunk_tokens = tokens[first];
+        while last + 1 < units.len() && chunk_tokens + tokens[last + 1] <= max_tokens {
+            last += 1;
+            chunk_tokens += tokens[last];
+        }
+        let (offset, end) = (units[first].0, units[last].1);
+        chunks.push(Chunk {
+            offset,
+            len: end - offset,
+            text: chars[offset..end].iter().collect(),
+        });
+        if last + 1 == units.len() {
+            return chunks;
+        }
+
+        // -- Step back for the overlap, always moving forward.
+        let mut next = last + 1;
+        let mut overlap = 0;
+        while next - 1 > first && overlap + tokens[next - 1] <= overlap_tokens {
+            next -= 1;
+            overlap += tokens[next];
+        }
+        first = next;
+    }
+}
+
+/// `(start, end)` of the sentences and lines, trailing whitespace included,
+/// cut at `max_chars`. Whitespace only units are dropped.
+fn split_units(chars: &[char], max_chars: usize) -> Vec<(usize, usize)> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let is_end = c == '\n'
+            || (matches!(c, '.' | '!' | '?')
+                && !matches!(chars.get(i + 1), Some(next) if !next.is_whitespace()));
+        i += 1;
+        if is_end {
+            // Keep the whitespace following the end with the unit.
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            units.push((start, i));
+            start = i;
+        }
+    }
+    if start < chars.len() {
+        units.push((start, chars.len()));
+    }
+
+    // -- Cut first, a cut may leave a whitespace only tail.
+    units
+        .into_iter()
+        .flat_map(|(start, end)| {
+            (start..end)
+                .step_by(max_chars)
+                .map(move |s| (s, (s + max_chars).min(end)))
+        })
+        .filter(|(start, end)| chars[*start..*end].iter().any(|c| !c.is_whitespace()))
+        .collect()
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn test_chunk_text_short_single() {
+        let chunks = chunk_text("Fix the login page.", 16, 4);
+        assert_eq!(
+            chunks,
+            vec![Chunk {
+                offset: 0,
+                len: 19,
+                text: "Fix the login page.".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chunk_text_overlap() {
+        // 4 sentences of 4, 4, 4 and 3 tokens.
+        let text = "Aaaa bbb cc. Dddd eee ff. Gggg hhh ii. Jjjj kkk ll.";
+        let chunks = chunk_text(text, 8, 4);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.trim_end()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Aaaa bbb cc. Dddd eee ff.",
+                "Dddd eee ff. Gggg hhh ii.",
+                "Gggg hhh ii. Jjjj kkk ll."
+            ]
+        );
+        for chunk in &chunks {
+            let from_offset: String = text.chars().skip(chunk.offset).take(chunk.len).collect();
+            assert_eq!(from_offset, chunk.text);
+        }
+    }
+
+    #[test]
+    fn test_chunk_text_long_unit_cut() {
+        let text = "x".repeat(20);
+        let chunks = chunk_text(&text, 2, 0); // 8 chars per unit
+        let lens: Vec<usize> = chunks.iter().map(|c| c.len).collect();
+        assert_eq!(lens, vec![8, 8, 4]);
+    }
+
+    #[test]
+    fn test_chunk_text_lines_and_unicode() {
+        let text = "Réunion du lundi\n\nTâches: déployer";
+        let chunks = chunk_text(text, 4, 0);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].text, "Tâches: déployer");
+        assert_eq!(chunks[1].offset, 18);
+    }
+}
+// endregion: --- Test
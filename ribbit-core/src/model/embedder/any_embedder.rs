@@ -0,0 +1,54 @@
+use crate::config::{config, EmbedderKind};
+
+#[cfg(feature = "local-embedder")]
+use super::CandleEmbedder;
+use super::{Embedder, HashingEmbedder, OpenAIEmbedder, Result};
+
+/// The `Embedder` selected by `embedder` in the config.
+#[derive(Clone)]
+pub enum AnyEmbedder {
+    OpenAI(OpenAIEmbedder),
+    Hashing(HashingEmbedder),
+    #[cfg(feature = "local-embedder")]
+    Local(CandleEmbedder),
+}
+
+impl Embedder for AnyEmbedder {
+    fn from_config() -> Self {
+        match config().embedder {
+            EmbedderKind::OpenAI => Self::OpenAI(OpenAIEmbedder::from_config()),
+            EmbedderKind::Hashing => Self::Hashing(HashingEmbedder::from_config()),
+            #[cfg(feature = "local-embedder")]
+            EmbedderKind::Local => Self::Local(CandleEmbedder::from_config()),
+            #[cfg(not(feature = "local-embedder"))]
+            EmbedderKind::Local => panic!("embedder `local` needs the `local-embedder` feature"),
+        }
+    }
+
+    fn model(&self) -> String {
+        match self {
+            Self::OpenAI(embedder) => embedder.model(),
+            Self::Hashing(embedder) => embedder.model(),
+            #[cfg(feature = "local-embedder")]
+            Self::Local(embedder) => embedder.model(),
+        }
+    }
+
+    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        match self {
+            Self::OpenAI(embedder) => embedder.embeds(texts).await,
+            Self::Hashing(embedder) => embedder.embeds(texts).await,
+            #[cfg(feature = "local-embedder")]
+            Self::Local(embedder) => embedder.embeds(texts).await,
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        match self {
+            Self::OpenAI(embedder) => embedder.embed(text).await,
+            Self::Hashing(embedder) => embedder.embed(text).await,
+            #[cfg(feature = "local-embedder")]
+            Self::Local(embedder) => embedder.embed(text).await,
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::future::Future;
+
+use futures::{stream, StreamExt, TryStreamExt};
+
+use crate::config::{EmbedderBatch, InputOverflow};
+use crate::model::store::normalize;
+
+use super::rate_limit::estimate_tokens;
+use super::{Error, Result};
+
+/// Chars per estimated token, the inverse of `estimate_tokens`.
+const CHARS_PER_TOKEN: usize = 4;
+
+///
+/// Split `texts` in provider sized batches, embed them with `embed_batch` (at most
+/// `concurrency` at a time) and return the embeddings in input order.
+///     - a batch holds at most `max_inputs` inputs and `max_tokens` estimated tokens
+///     - an input above `max_input_tokens` is truncated, chunked (mean of the chunks) or
+///       rejected, following `overflow`
+pub async fn embed_batched<F, Fut>(
+    texts: Vec<&str>,
+    conf: &EmbedderBatch,
+    embed_batch: F,
+) -> Result<Vec<Vec<f32>>>
+where
+    F: Fn(Vec<String>) -> Fut,
+    Fut: Future<Output = Result<Vec<Vec<f32>>>>,
+{
+    // -- Split the inputs into pieces, `owners[i]` is the input of piece `i`.
+    let mut pieces: Vec<String> = Vec::new();
+    let mut owners: Vec<usize> = Vec::new();
+    for (i, text) in texts.iter().enumerate() {
+        for piece in fit_input(text, conf)? {
+            pieces.push(piece);
+            owners.push(i);
+        }
+    }
+
+    // -- Plan the batches.
+    let mut batches: Vec<Vec<String>> = Vec::new();
+    let mut batch_tokens = 0;
+    for piece in pieces {
+        let tokens = estimate_tokens(&piece) as usize;
+        match batches.last_mut() {
+            Some(batch)
+                if batch.len() < conf.max_inputs && batch_tokens + tokens <= conf.max_tokens =>
+            {
+                batch_tokens += tokens;
+                batch.push(piece);
+            }
+            _ => {
+                batch_tokens = tokens;
+                batches.push(vec![piece]);
+            }
+        }
+    }
+
+    // -- Embed, `buffered` keeps the batch order.
+    let batch_embs: Vec<Vec<Vec<f32>>> = stream::iter(batches.into_iter().map(|batch| {
+        let expected = batch.len();
+        let fut = embed_batch(batch);
+        async move {
+            let embs = fut.await?;
+            if embs.len() != expected {
+                return Err(Error::EmbeddingCountMismatch {
+                    expected,
+                    actual: embs.len(),
+                });
+            }
+            Ok(embs)
+        }
+    }))
+    .buffered(conf.concurrency.max(1))
+    .try_collect()
+    .await?;
+
+    // -- Reassemble, the chunks of an input are averaged.
+    let mut embs: Vec<Vec<f32>> = Vec::with_capacity(texts.len());
+    let mut chunk_counts: Vec<usize> = Vec::with_capacity(texts.len());
+    for (owner, emb) in owners.into_iter().zip(batch_embs.into_iter().flatten()) {
+        if owner < embs.len() {
+            embs[owner]
+                .iter_mut()
+                .zip(&emb)
+                .for_each(|(acc, x)| *acc += x);
+            chunk_counts[owner] += 1;
+        } else {
+            embs.push(emb);
+            chunk_counts.push(1);
+        }
+    }
+    for (emb, count) in embs.iter_mut().zip(chunk_counts) {
+        if count > 1 {
+            normalize(emb);
+        }
+    }
+    Ok(embs)
+}
+
+/// The piece(s) of `text` to embed, each within `max_input_tokens`.
+fn fit_input(text: &str, conf: &EmbedderBatch) -> Result<Vec<String>> {
+    let tokens = estimate_tokens(text) as usize;
+    if tokens <= conf.max_input_tokens {
+        return Ok(vec![text.to_string()]);
+    }
+    let max_chars = conf.max_input_tokens.max(1) * CHARS_PER_TOKEN;
+    let chars: Vec<char> = text.chars().collect();
+    match conf.overflow {
+        InputOverflow::Truncate => Ok(vec![chars.iter().take(max_chars).collect()]),
+        InputOverflow::Chunk => Ok(chars
+            .chunks(max_chars)
+            .map(|chunk| chunk.iter().collect())
+            .collect()),
+        InputOverflow::Reject => Err(Error::InputTooLong {
+            tokens,
+            max_tokens: conf.max_input_tokens,
+        }),
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+
+    fn conf(overflow: InputOverflow) -> EmbedderBatch {
+        EmbedderBatch {
+            max_inputs: 2,
+            max_tokens: 3,
+            max_input_tokens: 2,
+            concurrency: 2,
+            overflow,
+        }
+    }
+
+    /// Embeds a text as `[len, 1]` and records the batches.
+    async fn run(
+        texts: Vec<&str>,
+        conf: &EmbedderBatch,
+    ) -> super::Result<(Vec<Vec<f32>>, Vec<Vec<String>>)> {
+        let batches = Mutex::new(Vec::new());
+        let embs = embed_batched(texts, conf, |batch| {
+            batches.lock().unwrap().push(batch.clone());
+            async move {
+                Ok(batch
+                    .iter()
+                    .map(|text| vec![text.len() as f32, 1.])
+                    .collect())
+            }
+        })
+        .await?;
+        Ok((embs, batches.into_inner().unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_embed_batched_order_ok() -> Result<()> {
+        let texts = vec!["a", "bb", "ccccc", "d", "e"];
+        let (embs, batches) = run(texts, &conf(InputOverflow::Truncate)).await?;
+        // At most 2 inputs and 3 tokens per batch.
+        assert_eq!(
+            batches,
+            vec![vec!["a", "bb"], vec!["ccccc", "d"], vec!["e"]]
+        );
+        let lens: Vec<f32> = embs.iter().map(|emb| emb[0]).collect();
+        assert_eq!(lens, vec![1., 2., 5., 1., 1.]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed_batched_overflow() -> Result<()> {
+        let text = "0123456789ab"; // 3 tokens, max 2
+
+        let (embs, _) = run(vec![text], &conf(InputOverflow::Truncate)).await?;
+        assert_eq!(embs, vec![vec![8., 1.]]);
+
+        let (embs, batches) = run(vec![text, "z"], &conf(InputOverflow::Chunk)).await?;
+        assert_eq!(batches.concat(), vec!["01234567", "89ab", "z"]);
+        assert_eq!(embs.len(), 2);
+        // mean of [8, 1] and [4, 1], normalized
+        let expected = [6., 1.].map(|x: f32| x / 37_f32.sqrt());
+        assert!((embs[0][0] - expected[0]).abs() < 1e-6);
+        assert_eq!(embs[1], vec![1., 1.]);
+
+        let res = run(vec![text], &conf(InputOverflow::Reject)).await;
+        assert!(matches!(res, Err(Error::InputTooLong { tokens: 3, .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit_input_truncate_shorter_than_max_chars() -> Result<()> {
+        let conf = EmbedderBatch {
+            max_input_tokens: 0,
+            ..conf(InputOverflow::Truncate)
+        };
+        assert_eq!(fit_input("abc", &conf)?, vec!["abc".to_string()]);
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::config;
+use crate::crypt::sha256_hex;
+use crate::model::store::{new_lazy_db_pool, Db};
+
+use super::{Embedder, Error, Result};
+
+///
+/// CachedEmbedder: wraps any `Embedder`, embeddings are keyed by (model, sha256(text)).
+///     - first the in process LRU, then the `embedding_cache` table (if any), then `inner`
+///     - a failing cache table is logged and skipped, it never fails the embedding
+#[derive(Clone)]
+pub struct CachedEmbedder<E: Embedder> {
+    inner: E,
+    model: String,
+    lru: Arc<Mutex<LruCache<String, Vec<f32>>>>,
+    db: Option<Db>,
+    counters: Arc<CacheCounters>,
+}
+
+#[derive(Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Texts served from the cache (`hits`) or embedded by the inner embedder (`misses`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<E: Embedder> CachedEmbedder<E> {
+    pub fn new(inner: E, capacity: usize, db: Option<Db>) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        CachedEmbedder {
+            model: inner.model(),
+            inner,
+            lru: Arc::new(Mutex::new(LruCache::new(capacity))),
+            db,
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn db_get(&self, db: &Db, hashes: &[String]) -> Vec<(String, Vec<f32>)> {
+        sqlx::query_as(
+            "
+            SELECT text_hash, embedding FROM embedding_cache
+            WHERE model = $1 AND text_hash = ANY($2)
+            ",
+        )
+        .bind(&self.model)
+        .bind(hashes)
+        .fetch_all(db)
+        .await
+        .unwrap_or_else(|ex| {
+            warn!("{:<12} - embedding_cache read failed: {ex}", "EMBEDDER");
+            Vec::new()
+        })
+    }
+
+    async fn db_put(&self, db: &Db, hashes: &[String], embs: &[Vec<f32>]) {
+        for (hash, emb) in hashes.iter().zip(embs) {
+            let res = sqlx::query(
+                "
+                INSERT INTO embedding_cache (model, text_hash, embedding) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+                ",
+            )
+            .bind(&self.model)
+            .bind(hash)
+            .bind(emb)
+            .execute(db)
+            .await;
+            if let Err(ex) = res {
+                warn!("{:<12} - embedding_cache write failed: {ex}", "EMBEDDER");
+                return;
+            }
+        }
+    }
+}
+
+impl<E: Embedder> Embedder for CachedEmbedder<E> {
+    fn from_config() -> Self {
+        let conf = &config().embedding_cache;
+        let db = conf.persist.then(|| {
+            new_lazy_db_pool()
+                .unwrap_or_else(|er| panic!("Failed to create embedding cache pool: {er}"))
+        });
+        Self::new(E::from_config(), conf.capacity, db)
+    }
+
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        let hashes: Vec<String> = texts.iter().map(|text| sha256_hex(text)).collect();
+        let mut found: HashMap<String, Vec<f32>> = HashMap::new();
+
+        // -- Lookup the LRU.
+        {
+            let mut lru = self.lru.lock().unwrap();
+            for hash in &hashes {
+                if let Some(emb) = lru.get(hash) {
+                    found.insert(hash.clone(), emb.clone());
+                }
+            }
+        }
+
+        // -- Lookup the table for the rest.
+        let mut missing: Vec<String> = Vec::new();
+        for hash in &hashes {
+            if !found.contains_key(hash) && !missing.contains(hash) {
+                missing.push(hash.clone());
+            }
+        }
+        if let (Some(db), false) = (&self.db, missing.is_empty()) {
+            let rows = self.db_get(db, &missing).await;
+            let mut lru = self.lru.lock().unwrap();
+            for (hash, emb) in rows {
+                lru.put(hash.clone(), emb.clone());
+                found.insert(hash, emb);
+            }
+        }
+
+        // -- Embed the rest, once per distinct text.
+        let mut to_embed: Vec<(String, &str)> = Vec::new();
+        for (hash, text) in hashes.iter().zip(&texts) {
+            if !found.contains_key(hash) && !to_embed.iter().any(|(h, _)| h == hash) {
+                to_embed.push((hash.clone(), text));
+            }
+        }
+        let misses = to_embed.len();
+        if !to_embed.is_empty() {
+            let (new_hashes, new_texts): (Vec<String>, Vec<&str>) = to_embed.into_iter().unzip();
+            let embs = self.inner.embeds(new_texts).await?;
+            if embs.len() != new_hashes.len() {
+                return Err(Error::EmbeddingCountMismatch {
+                    expected: new_hashes.len(),
+                    actual: embs.len(),
+                });
+            }
+            if let Some(db) = &self.db {
+                self.db_put(db, &new_hashes, &embs).await;
+            }
+            let mut lru = self.lru.lock().unwrap();
+            for (hash, emb) in new_hashes.into_iter().zip(embs) {
+                lru.put(hash.clone(), emb.clone());
+                found.insert(hash, emb);
+            }
+        }
+
+        self.counters
+            .hits
+            .fetch_add((texts.len() - misses) as u64, Ordering::Relaxed);
+        self.counters
+            .misses
+            .fetch_add(misses as u64, Ordering::Relaxed);
+
+        // Every hash is in `found` at this point.
+        Ok(hashes.iter().map(|hash| found[hash].clone()).collect())
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embeds(vec![text])
+            .await?
+            .pop()
+            .ok_or(Error::EmbeddingCountMismatch {
+                expected: 1,
+                actual: 0,
+            })
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::_dev_utils;
+    use crate::model::embedder::HashingEmbedder;
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    #[tokio::test]
+    async fn test_embeds_lru_ok() -> Result<()> {
+        let embedder = CachedEmbedder::new(HashingEmbedder::new(16), 2, None);
+        let embs = embedder.embeds(vec!["one", "two", "one"]).await?;
+        assert_eq!(embs[0], embs[2]);
+        assert_eq!(embedder.stats(), CacheStats { hits: 1, misses: 2 });
+
+        let emb = embedder.embed("two").await?;
+        assert_eq!(emb, embs[1]);
+        assert_eq!(embedder.stats(), CacheStats { hits: 2, misses: 2 });
+
+        // -- Capacity 2, "one" is evicted by "three".
+        embedder.embed("three").await?;
+        embedder.embed("one").await?;
+        assert_eq!(embedder.stats(), CacheStats { hits: 2, misses: 4 });
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_embeds_db_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let db = new_lazy_db_pool()?;
+        let text = "A story worth caching";
+        let embedder = CachedEmbedder::new(HashingEmbedder::new(16), 8, Some(db.clone()));
+        let emb = embedder.embed(text).await?;
+
+        // -- A new embedder (empty LRU) finds it in the table.
+        let embedder = CachedEmbedder::new(HashingEmbedder::new(16), 8, Some(db.clone()));
+        assert_eq!(embedder.embed(text).await?, emb);
+        assert_eq!(embedder.stats(), CacheStats { hits: 1, misses: 0 });
+
+        // -- Another model does not.
+        let embedder = CachedEmbedder::new(HashingEmbedder::new(8), 8, Some(db));
+        embedder.embed(text).await?;
+        assert_eq!(embedder.stats(), CacheStats { hits: 0, misses: 1 });
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config as BertConfig, DTYPE};
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+use crate::config::config;
+use crate::model::store::normalize;
+
+use super::{Embedder, Error, Result};
+
+///
+/// CandleEmbedder: sentence-transformer (BERT) model run on the CPU, nothing leaves the process.
+///     - `model_path` holds the `config.json`, `tokenizer.json` and `model.safetensors` of the model
+///     - the embedding is the mean of the token embeddings, L2 normalized
+///
+/// NOTE: Only built with the `local-embedder` feature.
+#[derive(Clone)]
+pub struct CandleEmbedder {
+    model_path: String,
+    model: Arc<CandleModel>,
+    batch_size: usize,
+}
+
+struct CandleModel {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+}
+
+impl CandleEmbedder {
+    /// Load the model, `dim` must match its hidden size.
+    pub fn load(model_path: &Path, dim: u64, batch_size: usize) -> Result<Self> {
+        let bert_conf: BertConfig = serde_json::from_str(
+            &fs::read_to_string(model_path.join("config.json")).map_err(load_err)?,
+        )
+        .map_err(load_err)?;
+        if bert_conf.hidden_size as u64 != dim {
+            return Err(Error::LocalModelDimMismatch {
+                expected: dim,
+                actual: bert_conf.hidden_size as u64,
+            });
+        }
+
+        let mut tokenizer =
+            Tokenizer::from_file(model_path.join("tokenizer.json")).map_err(load_err)?;
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..Default::default()
+        }));
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: bert_conf.max_position_embeddings,
+                ..Default::default()
+            }))
+            .map_err(load_err)?;
+
+        // SAFETY: The weights file is not expected to change while it is mapped.
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(
+                &[model_path.join("model.safetensors")],
+                DTYPE,
+                &Device::Cpu,
+            )
+        }
+        .map_err(load_err)?;
+        let bert = BertModel::load(vb, &bert_conf).map_err(load_err)?;
+
+        Ok(CandleEmbedder {
+            model_path: model_path.display().to_string(),
+            model: Arc::new(CandleModel { bert, tokenizer }),
+            batch_size: batch_size.max(1),
+        })
+    }
+}
+
+impl CandleModel {
+    /// Blocking, one forward pass for the whole batch.
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts, true)
+            .map_err(inference_err)?;
+        let (ids, masks): (Vec<Tensor>, Vec<Tensor>) = encodings
+            .iter()
+            .map(|enc| {
+                Ok((
+                    Tensor::new(enc.get_ids(), &Device::Cpu)?,
+                    Tensor::new(enc.get_attention_mask(), &Device::Cpu)?,
+                ))
+            })
+            .collect::<candle_core::Result<Vec<_>>>()
+            .map_err(inference_err)?
+            .into_iter()
+            .unzip();
+
+        let pooled = (|| {
+            let input_ids = Tensor::stack(&ids, 0)?;
+            let attention_mask = Tensor::stack(&masks, 0)?;
+            let token_type_ids = input_ids.zeros_like()?;
+            // (batch, seq, hidden)
+            let output = self
+                .bert
+                .forward(&input_ids, &token_type_ids, Some(&attention_mask))?;
+
+            // -- Mean pooling over the non padding tokens.
+            let mask = attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+            let sum = output.broadcast_mul(&mask)?.sum(1)?;
+            let count = mask.sum(1)?;
+            sum.broadcast_div(&count)?.to_vec2::<f32>()
+        })()
+        .map_err(inference_err)?;
+
+        Ok(pooled
+            .into_iter()
+            .map(|mut emb| {
+                normalize(&mut emb);
+                emb
+            })
+            .collect())
+    }
+}
+
+fn load_err(err: impl std::fmt::Display) -> Error {
+    Error::LocalModelLoadError(err.to_string())
+}
+
+fn inference_err(err: impl std::fmt::Display) -> Error {
+    Error::LocalModelInferenceError(err.to_string())
+}
+
+impl Embedder for CandleEmbedder {
+    fn from_config() -> Self {
+        let conf = config()
+            .local_embedder
+            .as_ref()
+            .expect("`local_embedder` missing in config");
+        CandleEmbedder::load(Path::new(&conf.model_path), conf.dim, conf.batch_size)
+            .unwrap_or_else(|er| panic!("Failed to load local embedder with error: {er}"))
+    }
+
+    fn model(&self) -> String {
+        format!("local:{}", self.model_path)
+    }
+
+    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        let mut embs = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            let model = self.model.clone();
+            let batch: Vec<String> = batch.iter().map(|text| text.to_string()).collect();
+            let batch_embs = tokio::task::spawn_blocking(move || model.embed_batch(batch))
+                .await
+                .map_err(inference_err)??;
+            embs.extend(batch_embs);
+        }
+        Ok(embs)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embeds(vec![text])
+            .await?
+            .pop()
+            .ok_or_else(|| inference_err("no embedding returned"))
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn test_embeds_batched_ok() -> Result<()> {
+        let embedder = CandleEmbedder::from_config();
+        let texts = vec![
+            "Fix the login page",
+            "Bake a chocolate cake",
+            "Once upon a time",
+        ];
+        let embs = embedder.embeds(texts.clone()).await?;
+        assert_eq!(embs.len(), 3);
+        assert_eq!(
+            embs[0].len() as u64,
+            config().local_embedder.as_ref().unwrap().dim
+        );
+
+        // -- Padding in the batch must not change the embedding.
+        let single = embedder.embed(texts[2]).await?;
+        let diff = single
+            .iter()
+            .zip(&embs[2])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f32::max);
+        assert!(diff < 1e-4, "diff {diff}");
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -1,9 +1,66 @@
+use std::time::Duration;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
+    EmbeddingCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    InputTooLong {
+        tokens: usize,
+        max_tokens: usize,
+    },
+    LocalModelLoadError(String),
+    LocalModelDimMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    LocalModelInferenceError(String),
+    // the env var named by `api_key_env` is unset or empty
+    OpenAIApiKeyMissing(String),
     // external
     OpenAIEmbedderRequestError(String),
+    // -- OpenAI, retryable
+    OpenAIRateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    OpenAIServerError {
+        status: u16,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    OpenAIConnectionError(String),
+    // -- OpenAI, fatal
+    OpenAIRequestRejected {
+        status: u16,
+        message: String,
+    },
+    OpenAIInvalidResponse(String),
+}
+
+impl Error {
+    /// Whether the same request may succeed later (rate limit, server or network failure).
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::OpenAIRateLimited { .. }
+                | Self::OpenAIServerError { .. }
+                | Self::OpenAIConnectionError(_)
+        )
+    }
+
+    /// The wait asked by the server, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::OpenAIRateLimited { retry_after, .. }
+            | Self::OpenAIServerError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
 }
 
 // region:    --- Error Boilerplate
@@ -0,0 +1,132 @@
+use crate::config::config;
+use crate::model::store::normalize;
+
+use super::{Embedder, Result};
+
+/// Weight of a char trigram relative to a word or word bigram.
+const TRIGRAM_WEIGHT: f32 = 0.5;
+
+///
+/// HashingEmbedder: offline, deterministic embedder for tests and air-gapped deployments.
+///     - features: words, word bigrams and char trigrams of the lowercased text
+///     - each feature is hashed (FNV-1a) into one of `dim` buckets, with a hashed sign
+///     - the vector is L2 normalized, so texts sharing features have a higher cosine score
+///
+/// NOTE: Lexical only, "cake" and "dessert" are unrelated for it.
+#[derive(Clone)]
+pub struct HashingEmbedder {
+    dim: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dim: usize) -> Self {
+        HashingEmbedder { dim }
+    }
+
+    fn embed_sync(&self, text: &str) -> Vec<f32> {
+        let mut emb = vec![0.; self.dim];
+        let text = text.to_lowercase();
+        let words: Vec<&str> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        for word in &words {
+            self.add(&mut emb, &format!("w:{word}"), 1.);
+            let chars: Vec<char> = format!(" {word} ").chars().collect();
+            for tri in chars.windows(3) {
+                let tri: String = tri.iter().collect();
+                self.add(&mut emb, &format!("c:{tri}"), TRIGRAM_WEIGHT);
+            }
+        }
+        for pair in words.windows(2) {
+            self.add(&mut emb, &format!("b:{} {}", pair[0], pair[1]), 1.);
+        }
+
+        normalize(&mut emb);
+        emb
+    }
+
+    fn add(&self, emb: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        let sign = if hash >> 63 == 1 { -1. } else { 1. };
+        emb[(hash % self.dim as u64) as usize] += sign * weight;
+    }
+}
+
+/// FNV-1a, spelled out since `DefaultHasher` is not guaranteed stable across releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Embedder for HashingEmbedder {
+    fn from_config() -> Self {
+        let conf = config()
+            .hashing_embedder
+            .as_ref()
+            .expect("`hashing_embedder` missing in config");
+        HashingEmbedder::new(conf.dim as usize)
+    }
+
+    fn model(&self) -> String {
+        format!("hashing-{}", self.dim)
+    }
+
+    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_sync(text)).collect())
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(self.embed_sync(text))
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[tokio::test]
+    async fn test_embed_deterministic() -> Result<()> {
+        let embedder = HashingEmbedder::new(256);
+        let emb = embedder.embed("Once upon a time").await?;
+        assert_eq!(emb.len(), 256);
+        assert_eq!(
+            emb,
+            HashingEmbedder::new(256).embed("Once upon a time").await?
+        );
+        let norm = cosine(&emb, &emb).sqrt();
+        assert!((norm - 1.).abs() < 1e-5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed_similar_ranks_higher() -> Result<()> {
+        let embedder = HashingEmbedder::new(256);
+        let embs = embedder
+            .embeds(vec![
+                "Fix the login page",
+                "fix login page bug",
+                "Bake a chocolate cake",
+            ])
+            .await?;
+        assert!(cosine(&embs[0], &embs[1]) > cosine(&embs[0], &embs[2]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed_empty_zero() -> Result<()> {
+        let emb = HashingEmbedder::new(8).embed(" ,. ").await?;
+        assert_eq!(emb, vec![0.; 8]);
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -1,75 +1,198 @@
+mod any_embedder;
+mod batching;
+mod cached_embedder;
+#[cfg(feature = "local-embedder")]
+mod candle_embedder;
 mod error;
+mod hashing_embedder;
+mod rate_limit;
+mod retry;
 
-use crate::config::config;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::config::{self, config};
+
+pub use self::any_embedder::AnyEmbedder;
+pub use self::cached_embedder::CachedEmbedder;
+#[cfg(feature = "local-embedder")]
+pub use self::candle_embedder::CandleEmbedder;
 pub use self::error::{Error, Result};
+pub use self::hashing_embedder::HashingEmbedder;
 
 use async_openai::{
-    config::OpenAIConfig,
-    types::{CreateEmbeddingRequestArgs, Embedding},
-    Client,
+    config::{Config as _, OpenAIConfig},
+    types::{CreateEmbeddingRequest, CreateEmbeddingRequestArgs, CreateEmbeddingResponse},
 };
+use tracing::warn;
+
+use self::batching::embed_batched;
+pub use self::rate_limit::estimate_tokens;
+use self::rate_limit::RateLimiter;
+use self::retry::{parse_retry_after, RetryPolicy};
 
-pub trait Embedder {
+// NOTE: The futures are declared `Send` so that embedders can be used from axum handlers.
+pub trait Embedder: Clone + Send + Sync + 'static {
     fn from_config() -> Self;
-    async fn embeds(&self, text: Vec<&str>) -> Result<Vec<Vec<f32>>>;
-    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+    /// Identifies the model, embeddings of different models are not comparable.
+    fn model(&self) -> String;
+    fn embeds(&self, text: Vec<&str>) -> impl Future<Output = Result<Vec<Vec<f32>>>> + Send;
+    fn embed(&self, text: &str) -> impl Future<Output = Result<Vec<f32>>> + Send;
 }
 
+const OPENAI_API_HOST: &str = "api.openai.com";
+
+/// Calls `/embeddings` itself (not through the async-openai client) to read the status
+/// and the `Retry-After` of the failures, see `retry` and `rate_limit`.
 #[derive(Clone)]
 pub struct OpenAIEmbedder {
-    client: Client<OpenAIConfig>,
+    http: reqwest::Client,
+    openai_config: OpenAIConfig,
     model: String,
+    retry: RetryPolicy,
+    limiter: Option<Arc<RateLimiter>>,
+    batch: config::EmbedderBatch,
 }
 
-impl Embedder for OpenAIEmbedder {
-    fn from_config() -> Self {
-        let client = Client::new();
-        let model = config().openai_embedder.model.to_string();
-        OpenAIEmbedder { client, model }
+impl OpenAIEmbedder {
+    /// Fails without an api key, unless `api_base` is another endpoint than OpenAI
+    /// (e.g. a local server without auth).
+    pub fn new(conf: &config::OpenAIEmbedder) -> Result<Self> {
+        let api_key = env::var(&conf.api_key_env).unwrap_or_default();
+        if api_key.is_empty() {
+            match conf.api_base.as_deref() {
+                Some(api_base) if !api_base.contains(OPENAI_API_HOST) => warn!(
+                    "{:<12} - {} not set, calling {api_base} without api key",
+                    "EMBEDDER", conf.api_key_env
+                ),
+                _ => return Err(Error::OpenAIApiKeyMissing(conf.api_key_env.clone())),
+            }
+        }
+        let mut openai_config = OpenAIConfig::new().with_api_key(api_key);
+        if let Some(api_base) = &conf.api_base {
+            openai_config = openai_config.with_api_base(api_base);
+        }
+        if let Some(org_id) = &conf.org_id {
+            openai_config = openai_config.with_org_id(org_id);
+        }
+
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout_secs) = conf.timeout_secs {
+            http = http.timeout(Duration::from_secs(timeout_secs));
+        }
+        let http = http
+            .build()
+            .unwrap_or_else(|er| panic!("Failed to build http client with error: {er}"));
+
+        Ok(OpenAIEmbedder {
+            http,
+            openai_config,
+            model: conf.model.to_string(),
+            retry: RetryPolicy::new(&conf.retry),
+            limiter: RateLimiter::from_conf(conf).map(Arc::new),
+            batch: conf.batch.clone(),
+        })
     }
-    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+
+    /// One batch, retried on the retryable failures.
+    async fn request(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let tokens = texts.iter().map(|text| estimate_tokens(text)).sum();
         let request = CreateEmbeddingRequestArgs::default()
             .model(&self.model)
             .input(texts)
             .build()
             .map_err(|e| Error::OpenAIEmbedderRequestError(e.to_string()))?;
 
-        let response = self
-            .client
-            .embeddings()
-            .create(request)
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire(tokens).await;
+            }
+            match self.send(&request).await {
+                Err(err) if err.is_retryable() && attempt < self.retry.max_retries => {
+                    let delay = self.retry.delay(attempt, err.retry_after());
+                    warn!(
+                        "{:<12} - OpenAIEmbedder retry {} in {delay:?} - {err:?}",
+                        "EMBEDDER",
+                        attempt + 1
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// One request, the failures are classified but not retried.
+    async fn send(&self, request: &CreateEmbeddingRequest) -> Result<Vec<Vec<f32>>> {
+        let res = self
+            .http
+            .post(self.openai_config.url("/embeddings"))
+            .query(&self.openai_config.query())
+            .headers(self.openai_config.headers())
+            .json(request)
+            .send()
             .await
-            .map_err(|e| {
-                Error::OpenAIEmbedderRequestError(format!("OpenAIEmbedder::embed: {:#?}", e))
-            })?;
+            .map_err(|e| Error::OpenAIConnectionError(e.to_string()))?;
+
+        let status = res.status();
+        if status.is_success() {
+            let mut response: CreateEmbeddingResponse = res
+                .json()
+                .await
+                .map_err(|e| Error::OpenAIInvalidResponse(e.to_string()))?;
+            response.data.sort_by_key(|d| d.index);
+            return Ok(response.data.into_iter().map(|d| d.embedding).collect());
+        }
 
-        let data: Vec<Vec<f32>> = response.data.iter().map(|d| d.embedding.clone()).collect();
-        Ok(data)
+        let retry_after = parse_retry_after(res.headers());
+        let message = res.text().await.unwrap_or_default();
+        let err = match status.as_u16() {
+            // An exhausted quota is a 429 too, but waiting won't help.
+            429 if message.contains("insufficient_quota") => Error::OpenAIRequestRejected {
+                status: 429,
+                message,
+            },
+            429 => Error::OpenAIRateLimited {
+                retry_after,
+                message,
+            },
+            status @ 500.. => Error::OpenAIServerError {
+                status,
+                retry_after,
+                message,
+            },
+            status => Error::OpenAIRequestRejected { status, message },
+        };
+        Err(err)
     }
+}
 
-    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(&self.model)
-            .input([text])
-            .build()
-            .map_err(|e| Error::OpenAIEmbedderRequestError(e.to_string()))?;
+impl Embedder for OpenAIEmbedder {
+    fn from_config() -> Self {
+        Self::new(&config().openai_embedder)
+            .unwrap_or_else(|er| panic!("Failed to create OpenAIEmbedder with error: {er}"))
+    }
 
-        let response = self
-            .client
-            .embeddings()
-            .create(request)
-            .await
-            .map_err(|e| {
-                Error::OpenAIEmbedderRequestError(format!("OpenAIEmbedder::embed: {:#?}", e))
-            })?;
+    fn model(&self) -> String {
+        self.model.clone()
+    }
 
-        let data = response.data.first().ok_or_else(|| {
-            Error::OpenAIEmbedderRequestError(
-                "OpenAIEmbedder::embed: response.data.first() is None".to_string(),
-            )
-        })?;
-        Ok(data.embedding.clone())
+    async fn embeds(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        embed_batched(texts, &self.batch, |batch| self.request(batch)).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embeds(vec![text])
+            .await?
+            .pop()
+            .ok_or(Error::EmbeddingCountMismatch {
+                expected: 1,
+                actual: 0,
+            })
     }
 }
 
@@ -79,6 +202,145 @@ mod tests {
     #[allow(unused)]
     use super::*;
     use anyhow::Result;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn embeddings_body(body: &Value) -> Value {
+        let data: Vec<Value> = body["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(index, _)| json!({"object": "embedding", "index": index, "embedding": [0.5, 0.5]}))
+            .collect();
+        json!({
+            "object": "list",
+            "model": body["model"],
+            "data": data,
+            "usage": {"prompt_tokens": 1, "total_tokens": 1},
+        })
+    }
+
+    /// Minimal `/embeddings` endpoint, checking the credentials sent by the client.
+    async fn mock_embeddings(headers: HeaderMap, Json(body): Json<Value>) -> Response {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        if header("authorization") != Some("Bearer mock-key")
+            || header("openai-organization") != Some("mock-org")
+        {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Json(embeddings_body(&body)).into_response()
+    }
+
+    /// Rate limited on the first call, bad request from the `"bad"` input.
+    async fn mock_flaky(
+        State(calls): State<Arc<AtomicUsize>>,
+        Json(body): Json<Value>,
+    ) -> Response {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        if body["input"][0] == "bad" {
+            return (StatusCode::BAD_REQUEST, "invalid input").into_response();
+        }
+        if call == 0 {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [("retry-after-ms", "10")],
+                "rate limited",
+            )
+                .into_response();
+        }
+        Json(embeddings_body(&body)).into_response()
+    }
+
+    async fn serve_mock(app: Router) -> Result<config::OpenAIEmbedder> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(axum::Server::from_tcp(listener)?.serve(app.into_make_service()));
+
+        env::set_var("RIBBIT_MOCK_OPENAI_KEY", "mock-key");
+        Ok(config::OpenAIEmbedder {
+            model: "mock-model".to_string(),
+            api_base: Some(format!("http://{addr}/v1")),
+            api_key_env: "RIBBIT_MOCK_OPENAI_KEY".to_string(),
+            org_id: Some("mock-org".to_string()),
+            timeout_secs: Some(5),
+            retry: config::EmbedderRetry::default(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            batch: config::EmbedderBatch::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_embeds_mock_server_ok() -> Result<()> {
+        let app = Router::new().route("/v1/embeddings", post(mock_embeddings));
+        let embedder = OpenAIEmbedder::new(&serve_mock(app).await?)?;
+        let embs = embedder.embeds(vec!["one", "two"]).await?;
+        assert_eq!(embs, vec![vec![0.5, 0.5], vec![0.5, 0.5]]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embeds_retry_rate_limited_ok() -> Result<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/v1/embeddings", post(mock_flaky))
+            .with_state(calls.clone());
+        let embedder = OpenAIEmbedder::new(&serve_mock(app).await?)?;
+        let emb = embedder.embed("one").await?;
+        assert_eq!(emb, vec![0.5, 0.5]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embeds_err_fatal_not_retried() -> Result<()> {
+        let calls = Arc::new(AtomicUsize::new(1)); // skip the rate limited call
+        let app = Router::new()
+            .route("/v1/embeddings", post(mock_flaky))
+            .with_state(calls.clone());
+        let embedder = OpenAIEmbedder::new(&serve_mock(app).await?)?;
+        let res = embedder.embed("bad").await;
+        assert!(
+            matches!(res, Err(Error::OpenAIRequestRejected { status: 400, .. })),
+            "{res:?}"
+        );
+        assert!(!res.unwrap_err().is_retryable());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_err_api_key_missing() -> Result<()> {
+        let conf = |api_base: Option<&str>| config::OpenAIEmbedder {
+            model: "mock-model".to_string(),
+            api_base: api_base.map(|base| base.to_string()),
+            api_key_env: "RIBBIT_UNSET_OPENAI_KEY".to_string(),
+            org_id: None,
+            timeout_secs: None,
+            retry: config::EmbedderRetry::default(),
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            batch: config::EmbedderBatch::default(),
+        };
+
+        for api_base in [None, Some("https://api.openai.com/v1")] {
+            let res = OpenAIEmbedder::new(&conf(api_base));
+            assert!(
+                matches!(&res, Err(Error::OpenAIApiKeyMissing(env)) if env == "RIBBIT_UNSET_OPENAI_KEY"),
+                "{:?}",
+                res.err()
+            );
+        }
+        // -- A keyless endpoint.
+        OpenAIEmbedder::new(&conf(Some("http://localhost:8081/v1")))?;
+        Ok(())
+    }
 
     #[tokio::test]
     async fn test_embed_ok() -> Result<()> {
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::OpenAIEmbedder as OpenAIEmbedderConfig;
+
+/// Client side limit of the requests and the (estimated) tokens per minute.
+pub struct RateLimiter {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    /// `None` when no limit is configured.
+    pub fn from_conf(conf: &OpenAIEmbedderConfig) -> Option<Self> {
+        if conf.requests_per_minute.is_none() && conf.tokens_per_minute.is_none() {
+            return None;
+        }
+        Some(RateLimiter {
+            requests: conf.requests_per_minute.map(TokenBucket::per_minute),
+            tokens: conf.tokens_per_minute.map(TokenBucket::per_minute),
+        })
+    }
+
+    /// Wait until one request of `tokens` tokens is allowed.
+    pub async fn acquire(&self, tokens: u32) {
+        if let Some(bucket) = &self.requests {
+            bucket.acquire(1.).await;
+        }
+        if let Some(bucket) = &self.tokens {
+            bucket.acquire(tokens as f64).await;
+        }
+    }
+}
+
+/// Rough token count (~4 chars per token), enough for limits and batching.
+pub fn estimate_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4).max(1)
+}
+
+struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(per_minute: u32) -> Self {
+        let capacity = per_minute.max(1) as f64;
+        TokenBucket {
+            capacity,
+            refill_per_sec: capacity / 60.,
+            state: Mutex::new(BucketState {
+                available: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `amount`, waiting for the refill if needed.
+    /// An amount above the capacity is taken as the full capacity, so it can't wait forever.
+    async fn acquire(&self, amount: f64) {
+        let amount = amount.min(self.capacity);
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                state.available =
+                    (state.available + elapsed * self.refill_per_sec).min(self.capacity);
+                state.refilled_at = now;
+                if state.available >= amount {
+                    state.available -= amount;
+                    return;
+                }
+                Duration::from_secs_f64((amount - state.available) / self.refill_per_sec)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 1);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket_waits_for_refill() {
+        let bucket = TokenBucket::per_minute(60); // 1 per sec
+        let start = tokio::time::Instant::now();
+        bucket.acquire(60.).await;
+        assert!(start.elapsed() < Duration::from_millis(10));
+
+        // -- Empty now, 2 tokens take ~2s.
+        bucket.acquire(2.).await;
+        assert!(start.elapsed() >= Duration::from_secs(2));
+    }
+}
+// endregion: --- Test
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::HeaderMap;
+
+use crate::config::EmbedderRetry;
+
+/// Exponential backoff with full jitter, `Retry-After` wins when the server sends one.
+/// Both are capped at `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(conf: &EmbedderRetry) -> Self {
+        RetryPolicy {
+            max_retries: conf.max_retries,
+            base_delay: Duration::from_millis(conf.base_delay_ms),
+            max_delay: Duration::from_millis(conf.max_delay_ms),
+        }
+    }
+
+    /// Delay before the retry following the failed `attempt` (0 based).
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=self.backoff_cap(attempt))
+    }
+
+    /// Upper bound of the jittered delay: `base * 2^attempt`, capped at `max_delay`.
+    fn backoff_cap(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// `retry-after-ms` (OpenAI) or `retry-after` in seconds.
+///
+/// NOTE: The HTTP-date form of `retry-after` is ignored, the backoff applies then.
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.)
+    };
+    // An overflowing value is no valid header, it must not panic.
+    let secs = |secs: f64| Duration::try_from_secs_f64(secs).ok();
+    match header("retry-after-ms") {
+        Some(ms) => secs(ms / 1000.),
+        None => header("retry-after").and_then(secs),
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_delay_capped() {
+        let policy = policy();
+        assert_eq!(policy.backoff_cap(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff_cap(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff_cap(40), Duration::from_secs(1));
+        for attempt in 0..10 {
+            assert!(policy.delay(attempt, None) <= Duration::from_secs(1));
+        }
+        let retry_after = Some(Duration::from_millis(700));
+        assert_eq!(policy.delay(0, retry_after), Duration::from_millis(700));
+        let retry_after = Some(Duration::from_secs(86400));
+        assert_eq!(policy.delay(0, retry_after), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_parse_retry_after_ok() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(
+            parse_retry_after(&headers),
+            Some(Duration::from_millis(1500))
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after_huge() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "1e300".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "86400".parse().unwrap());
+        let retry_after = parse_retry_after(&headers);
+        assert_eq!(retry_after, Some(Duration::from_secs(86400)));
+        assert_eq!(policy().delay(0, retry_after), Duration::from_secs(1));
+    }
+}
+// endregion: --- Test
@@ -8,6 +8,20 @@ pub enum Error {
     Embedder(embedder::Error),
     Sqlx(sqlx::Error),
     EntityNotFound { entity: &'static str, id: i64 },
+    OutboxInvalidOp(String),
+    // the chunk index has 16 bits of the point id
+    TaskTooManyChunks { chunks: usize, max_chunks: usize },
+
+    // -- Migrations
+    MigrationDirRead(String),
+    MigrationInvalidFileName(String),
+    MigrationDuplicate(i64),
+    MigrationMissingUp(i64),
+    MigrationMissingDown(i64),
+    // applied, but no longer in the migrations dir
+    MigrationNotFound(i64),
+    // the up script changed since it was applied
+    MigrationChecksumMismatch(i64),
 }
 
 // region:    --- Error Boilerplate
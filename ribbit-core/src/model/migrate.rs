@@ -0,0 +1,374 @@
+//! Migrate: versioned schema changes, read from `config().database.migrations_dir`.
+//!     - `{version}_{name}.up.sql` applies the change, the optional `.down.sql` reverts it
+//!     - the applied ones are recorded in `schema_migration` with the sha256 of their up script,
+//!       an applied script that changed since is an error
+//!     - a script runs whole (no split on `;`) in one transaction with its record,
+//!       so functions, strings and DO blocks are fine
+//!
+//! NOTE: A run holds an advisory lock, instances migrating on start wait for each other.
+//! NOTE: Statements that can't run in a transaction (`CREATE INDEX CONCURRENTLY`) are not supported.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use sqlx::{Connection, Executor, PgConnection};
+use tracing::info;
+
+use crate::crypt::sha256_hex;
+use crate::model::error::{Error, Result};
+use crate::model::store::Db;
+
+const MIGRATION_LOCK_KEY: i64 = 0x5249_4242_4954; // "RIBBIT"
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub up: String,
+    pub down: Option<String>,
+    /// sha256 hex of `up`.
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied: bool,
+}
+
+/// The migrations of `dir`, by version. The other files are ignored.
+pub fn load(dir: impl AsRef<Path>) -> Result<Vec<Migration>> {
+    let dir = dir.as_ref();
+    let read_err = |ex: std::io::Error| Error::MigrationDirRead(format!("{}: {ex}", dir.display()));
+    let mut ups: BTreeMap<i64, (String, String)> = BTreeMap::new();
+    let mut downs: BTreeMap<i64, String> = BTreeMap::new();
+    for entry in fs::read_dir(dir).map_err(read_err)? {
+        let path = entry.map_err(read_err)?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let (stem, is_up) = match (
+            file_name.strip_suffix(".up.sql"),
+            file_name.strip_suffix(".down.sql"),
+        ) {
+            (Some(stem), _) => (stem, true),
+            (_, Some(stem)) => (stem, false),
+            _ => continue,
+        };
+        let (version, name) = parse_stem(stem)
+            .ok_or_else(|| Error::MigrationInvalidFileName(file_name.to_string()))?;
+        let sql = fs::read_to_string(&path).map_err(read_err)?;
+        let duplicate = match is_up {
+            true => ups.insert(version, (name, sql)).is_some(),
+            false => downs.insert(version, sql).is_some(),
+        };
+        if duplicate {
+            return Err(Error::MigrationDuplicate(version));
+        }
+    }
+    if let Some(version) = downs.keys().find(|version| !ups.contains_key(version)) {
+        return Err(Error::MigrationMissingUp(*version));
+    }
+
+    Ok(ups
+        .into_iter()
+        .map(|(version, (name, up))| Migration {
+            version,
+            name,
+            checksum: sha256_hex(&up),
+            up,
+            down: downs.remove(&version),
+        })
+        .collect())
+}
+
+/// `0002_story_tags` -> `(2, "story_tags")`.
+fn parse_stem(stem: &str) -> Option<(i64, String)> {
+    let (version, name) = stem.split_once('_')?;
+    let version: i64 = version.parse().ok()?;
+    (version > 0 && !name.is_empty()).then(|| (version, name.to_string()))
+}
+
+/// Apply the pending migrations of `dir`, by version. Returns the applied versions.
+pub async fn up(db: &Db, dir: impl AsRef<Path>) -> Result<Vec<i64>> {
+    let migrations = load(dir)?;
+    let mut conn = db.acquire().await?;
+    lock(&mut conn).await?;
+    let res = up_locked(&mut conn, &migrations).await;
+    unlock(&mut conn).await?;
+    res
+}
+
+/// Revert the last `steps` applied migrations, latest first. Returns the reverted versions.
+pub async fn down(db: &Db, dir: impl AsRef<Path>, steps: usize) -> Result<Vec<i64>> {
+    let migrations = load(dir)?;
+    let mut conn = db.acquire().await?;
+    lock(&mut conn).await?;
+    let res = down_locked(&mut conn, &migrations, steps).await;
+    unlock(&mut conn).await?;
+    res
+}
+
+pub async fn status(db: &Db, dir: impl AsRef<Path>) -> Result<Vec<MigrationStatus>> {
+    let migrations = load(dir)?;
+    let mut conn = db.acquire().await?;
+    lock(&mut conn).await?;
+    let res = applied_checked(&mut conn, &migrations).await;
+    unlock(&mut conn).await?;
+    let applied = res?;
+    Ok(migrations
+        .into_iter()
+        .map(|m| MigrationStatus {
+            applied: applied.contains_key(&m.version),
+            version: m.version,
+            name: m.name,
+        })
+        .collect())
+}
+
+async fn up_locked(conn: &mut PgConnection, migrations: &[Migration]) -> Result<Vec<i64>> {
+    let applied = applied_checked(conn, migrations).await?;
+    let mut versions = Vec::new();
+    for m in migrations
+        .iter()
+        .filter(|m| !applied.contains_key(&m.version))
+    {
+        info!("{:<12} - up {} {}", "MIGRATE", m.version, m.name);
+        let mut tx = conn.begin().await?;
+        (&mut *tx).execute(m.up.as_str()).await?;
+        sqlx::query("INSERT INTO schema_migration (version, name, checksum) VALUES ($1, $2, $3)")
+            .bind(m.version)
+            .bind(&m.name)
+            .bind(&m.checksum)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        versions.push(m.version);
+    }
+    Ok(versions)
+}
+
+async fn down_locked(
+    conn: &mut PgConnection,
+    migrations: &[Migration],
+    steps: usize,
+) -> Result<Vec<i64>> {
+    let applied = applied_checked(conn, migrations).await?;
+    // -- All the down scripts must exist before reverting any.
+    let reverts = applied
+        .keys()
+        .rev()
+        .take(steps)
+        .map(|version| {
+            let m = migrations.iter().find(|m| m.version == *version);
+            let down = m.and_then(|m| m.down.as_ref());
+            down.map(|down| (*version, down))
+                .ok_or(Error::MigrationMissingDown(*version))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut versions = Vec::new();
+    for (version, down) in reverts {
+        info!("{:<12} - down {version}", "MIGRATE");
+        let mut tx = conn.begin().await?;
+        (&mut *tx).execute(down.as_str()).await?;
+        sqlx::query("DELETE FROM schema_migration WHERE version = $1")
+            .bind(version)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        versions.push(version);
+    }
+    Ok(versions)
+}
+
+/// The applied versions and checksums, checked against `migrations`.
+async fn applied_checked(
+    conn: &mut PgConnection,
+    migrations: &[Migration],
+) -> Result<BTreeMap<i64, String>> {
+    sqlx::query(
+        "
+        CREATE TABLE IF NOT EXISTS schema_migration (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(128) NOT NULL,
+            checksum VARCHAR(64) NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
+        ",
+    )
+    .execute(&mut *conn)
+    .await?;
+    let applied: BTreeMap<i64, String> =
+        sqlx::query_as::<_, (i64, String)>("SELECT version, checksum FROM schema_migration")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .collect();
+
+    for (version, checksum) in &applied {
+        let m = migrations
+            .iter()
+            .find(|m| m.version == *version)
+            .ok_or(Error::MigrationNotFound(*version))?;
+        if &m.checksum != checksum {
+            return Err(Error::MigrationChecksumMismatch(*version));
+        }
+    }
+    Ok(applied)
+}
+
+async fn lock(conn: &mut PgConnection) -> Result<()> {
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn unlock(conn: &mut PgConnection) -> Result<()> {
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::_dev_utils;
+    use crate::config::config;
+    use crate::model::store::new_db_pool;
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    /// Empty temp dir with the `files`.
+    fn temp_dir(files: &[(&str, &str)]) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("ribbit_migrate_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir)?;
+        for (name, content) in files {
+            fs::write(dir.join(name), content)?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_load_ok() -> Result<()> {
+        let dir = temp_dir(&[
+            ("0002_b.up.sql", "SELECT 2;"),
+            ("0001_a.up.sql", "SELECT 1;"),
+            ("0001_a.down.sql", "SELECT -1;"),
+            ("README.md", "not a migration"),
+        ])?;
+        let migrations = load(&dir)?;
+        fs::remove_dir_all(&dir)?;
+
+        let versions: Vec<i64> = migrations.iter().map(|m| m.version).collect();
+        assert_eq!(versions, vec![1, 2]);
+        assert_eq!(migrations[0].name, "a");
+        assert_eq!(migrations[0].down.as_deref(), Some("SELECT -1;"));
+        assert!(migrations[1].down.is_none());
+        assert_eq!(migrations[1].checksum, sha256_hex("SELECT 2;"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_err() -> Result<()> {
+        let load_files = |files: &[(&str, &str)]| -> Result<_> {
+            let dir = temp_dir(files)?;
+            let res = load(&dir);
+            fs::remove_dir_all(&dir)?;
+            Ok(res)
+        };
+
+        let res = load_files(&[("one_a.up.sql", "")])?;
+        assert!(
+            matches!(res, Err(Error::MigrationInvalidFileName(_))),
+            "{res:?}"
+        );
+        let res = load_files(&[("0001_a.down.sql", "")])?;
+        assert!(matches!(res, Err(Error::MigrationMissingUp(1))), "{res:?}");
+        let res = load_files(&[("0001_a.up.sql", ""), ("0001_b.up.sql", "")])?;
+        assert!(matches!(res, Err(Error::MigrationDuplicate(1))), "{res:?}");
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_up_down_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let db = new_db_pool().await?;
+        let base: Vec<Migration> = load(&config().database.migrations_dir)?;
+
+        // -- The app migrations plus one with a DO block and a function.
+        let mut files: Vec<(String, String)> = Vec::new();
+        for m in &base {
+            files.push((format!("{:04}_{}.up.sql", m.version, m.name), m.up.clone()));
+            if let Some(down) = &m.down {
+                files.push((
+                    format!("{:04}_{}.down.sql", m.version, m.name),
+                    down.clone(),
+                ));
+            }
+        }
+        files.push((
+            "9001_migrate_test.up.sql".to_string(),
+            "
+            CREATE TABLE migrate_test (id INT);
+            CREATE FUNCTION migrate_test_fn() RETURNS TEXT AS $$ SELECT 'a;b' $$ LANGUAGE SQL;
+            DO $$ BEGIN INSERT INTO migrate_test VALUES (1); END $$;
+            "
+            .to_string(),
+        ));
+        files.push((
+            "9001_migrate_test.down.sql".to_string(),
+            "DROP FUNCTION migrate_test_fn(); DROP TABLE migrate_test;".to_string(),
+        ));
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(name, sql)| (name.as_str(), sql.as_str()))
+            .collect();
+        let dir = temp_dir(&files)?;
+
+        // -- Up, only the new one, once.
+        assert_eq!(up(&db, &dir).await?, vec![9001]);
+        assert!(up(&db, &dir).await?.is_empty());
+        let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM migrate_test")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(count, 1);
+        assert!(status(&db, &dir).await?.iter().all(|s| s.applied));
+
+        // -- Down.
+        assert_eq!(down(&db, &dir, 1).await?, vec![9001]);
+        let (table,): (Option<String>,) =
+            sqlx::query_as("SELECT to_regclass('migrate_test')::TEXT")
+                .fetch_one(&db)
+                .await?;
+        assert!(table.is_none());
+
+        // -- An applied script must not change.
+        let first = &base[0];
+        fs::write(
+            dir.join(format!("{:04}_{}.up.sql", first.version, first.name)),
+            "SELECT 1;",
+        )?;
+        let res = up(&db, &dir).await;
+        fs::remove_dir_all(&dir)?;
+        assert!(
+            matches!(res, Err(Error::MigrationChecksumMismatch(v)) if v == first.version),
+            "{res:?}"
+        );
+        Ok(())
+    }
+}
+// endregion: --- Test
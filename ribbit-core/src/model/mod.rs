@@ -1,28 +1,36 @@
 // region:   --- Modules
 
-mod embedder;
+pub mod chunker;
+pub mod embedder;
 mod error;
-mod store;
-mod task;
+pub mod migrate;
+pub mod outbox;
+pub mod reconcile;
+pub mod reindex;
+pub mod store;
+pub mod task;
+pub mod user;
 
 pub use self::embedder::Embedder;
-use self::error::Result;
-pub use self::store::{new_db_pool, Db, VecStore};
+pub use self::error::{Error, Result};
+pub use self::store::{new_db_pool, AnyVecStore, Db, MemVecStore, VectorBackend};
 
 // endregion: --- Modules
 
+/// `V` defaults to the backend selected in the config (`vector_backend`).
 #[derive(Clone)]
-pub struct ModelManager<E: Embedder> {
+pub struct ModelManager<E: Embedder, V: VectorBackend = AnyVecStore> {
     pub db: Db,
-    pub vs: VecStore,
+    pub vs: V,
     pub embedder: E,
 }
 
-impl<E: Embedder> ModelManager<E> {
+impl<E: Embedder, V: VectorBackend> ModelManager<E, V> {
     pub async fn from_config() -> Result<Self> {
-        let vs = VecStore::from_config().await?;
+        let db = new_db_pool().await?;
+        let vs = V::from_config_with_db(db.clone()).await?;
         Ok(ModelManager {
-            db: new_db_pool().await?,
+            db,
             vs,
             embedder: E::from_config(),
         })
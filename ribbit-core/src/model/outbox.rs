@@ -0,0 +1,491 @@
+//! Outbox: every story change writes an outbox entry in the same transaction,
+//! the worker then applies it to the vector store, retrying until it succeeds.
+//!     - a worker claims all the entries of a story at once, for `CLAIM_TTL_SECS`,
+//!       so one story is applied by one worker at a time, in order
+//!     - the entries of a story are coalesced, the latest op wins
+//!     - the claim is a short transaction, no lock is held during the embedding and store calls
+//!     - a failed story is retried with an exponential backoff capped at `MAX_BACKOFF_SECS`,
+//!       and given up (`dead_at`) after `MAX_ATTEMPTS`, a later change of the story retries it
+//!
+//! NOTE: A worker slower than `CLAIM_TTL_SECS` may see the story applied twice, which is idempotent.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use sqlx::{FromRow, PgConnection};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
+
+use crate::model::error::{Error, Result};
+use crate::model::task::{TaskBmc, VsBmc};
+use crate::model::{ModelManager, VectorBackend};
+
+use super::embedder::Embedder;
+
+const OUTBOX_BATCH_SIZE: i64 = 32;
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const CLAIM_TTL_SECS: f64 = 300.;
+const MAX_BACKOFF_SECS: f64 = 300.;
+const WARN_ATTEMPTS: i32 = 10;
+const MAX_ATTEMPTS: i32 = 20;
+/// Serializes the claims of the workers, see `OutboxBmc::claim`.
+const OUTBOX_CLAIM_LOCK_KEY: i64 = 0x4f55_5442_4f58; // "OUTBOX"
+
+pub struct OutboxBmc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxOp {
+    Upsert,
+    Delete,
+}
+
+impl OutboxOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Upsert => "upsert",
+            Self::Delete => "delete",
+        }
+    }
+
+    pub fn parse(op: &str) -> Result<Self> {
+        match op {
+            "upsert" => Ok(Self::Upsert),
+            "delete" => Ok(Self::Delete),
+            _ => Err(Error::OutboxInvalidOp(op.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub story_id: i64,
+    pub op: String,
+    pub attempts: i32,
+}
+
+/// The claimed entries of one story, coalesced.
+#[derive(Debug)]
+struct StoryChange {
+    story_id: i64,
+    /// The latest entry, its op wins.
+    last: OutboxEntry,
+    attempts: i32,
+    entry_count: usize,
+}
+
+// endregion: --- Outbox Types
+
+impl OutboxBmc {
+    /// Must be called with the transaction of the story change.
+    pub async fn enqueue(
+        conn: &mut PgConnection,
+        story_id: i64,
+        owner_id: i64,
+        op: OutboxOp,
+    ) -> Result<()> {
+        sqlx::query(
+            "
+            INSERT INTO story_outbox (story_id, owner_id, op) VALUES ($1, $2, $3)
+            ",
+        )
+        .bind(story_id)
+        .bind(owner_id)
+        .bind(op.as_str())
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Apply one batch of due stories. Returns the number of entries handled,
+    /// failed ones included (they are rescheduled or given up).
+    ///
+    /// NOTE: Several workers can run side by side, a story is claimed by one of them.
+    pub async fn process_pending(
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+    ) -> Result<usize> {
+        let changes = Self::claim(&mm).await?;
+
+        let mut handled = 0;
+        for change in changes {
+            handled += change.entry_count;
+            match Self::apply(&mm, &change).await {
+                Ok(()) => {
+                    // The entries enqueued since the claim are kept.
+                    sqlx::query("DELETE FROM story_outbox WHERE story_id = $1 AND id <= $2")
+                        .bind(change.story_id)
+                        .bind(change.last.id)
+                        .execute(&mm.db)
+                        .await?;
+                }
+                Err(ex) => {
+                    let attempts = change.attempts + 1;
+                    let give_up = attempts >= MAX_ATTEMPTS;
+                    if give_up {
+                        error!(
+                            "{:<12} - story {} given up after {attempts} attempts: {ex:?}",
+                            "OUTBOX", change.story_id
+                        );
+                    } else if attempts >= WARN_ATTEMPTS {
+                        warn!(
+                            "{:<12} - story {} failed {attempts} times",
+                            "OUTBOX", change.story_id
+                        );
+                    }
+                    sqlx::query(
+                        "
+                        UPDATE story_outbox
+                        SET attempts = $3, last_error = $4, claimed_until = NULL,
+                            next_attempt_at = now() + make_interval(secs => $5),
+                            dead_at = CASE WHEN $6 THEN now() END
+                        WHERE story_id = $1 AND id <= $2 AND dead_at IS NULL
+                        ",
+                    )
+                    .bind(change.story_id)
+                    .bind(change.last.id)
+                    .bind(attempts)
+                    .bind(ex.to_string())
+                    .bind(backoff_secs(attempts))
+                    .bind(give_up)
+                    .execute(&mm.db)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(handled)
+    }
+
+    /// Process batches until no entry is due.
+    pub async fn process_all(mm: ModelManager<impl Embedder, impl VectorBackend>) -> Result<usize> {
+        let mut handled = 0;
+        loop {
+            match Self::process_pending(mm.clone()).await? {
+                0 => return Ok(handled),
+                count => handled += count,
+            }
+        }
+    }
+
+    /// Claim the live entries of up to `OUTBOX_BATCH_SIZE` due stories that no other worker holds.
+    async fn claim(
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+    ) -> Result<Vec<StoryChange>> {
+        let mut tx = mm.db.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(OUTBOX_CLAIM_LOCK_KEY)
+            .execute(&mut *tx)
+            .await?;
+        let entries: Vec<OutboxEntry> = sqlx::query_as(
+            "
+            WITH due AS (
+                SELECT story_id FROM story_outbox
+                WHERE dead_at IS NULL
+                GROUP BY story_id
+                HAVING min(next_attempt_at) <= now()
+                    AND bool_and(claimed_until IS NULL OR claimed_until <= now())
+                ORDER BY min(id)
+                LIMIT $1
+            )
+            UPDATE story_outbox o
+            SET claimed_until = now() + make_interval(secs => $2)
+            FROM due
+            WHERE o.story_id = due.story_id AND o.dead_at IS NULL
+            RETURNING o.id, o.story_id, o.op, o.attempts
+            ",
+        )
+        .bind(OUTBOX_BATCH_SIZE)
+        .bind(CLAIM_TTL_SECS)
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(coalesce(entries))
+    }
+
+    async fn apply(
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+        change: &StoryChange,
+    ) -> Result<()> {
+        let story_id = change.story_id;
+        if OutboxOp::parse(&change.last.op)? == OutboxOp::Delete {
+            mm.vs
+                .delete_task_points(TaskBmc::COLLECTION_NAME, vec![story_id])
+                .await?;
+            return Ok(());
+        }
+
+        // -- Upsert the current story, the connection is released before the embedding.
+        let src = {
+            let mut conn = mm.db.acquire().await?;
+            TaskBmc::source(&mut conn, story_id).await?
+        };
+        if let Some(src) = src {
+            // -- Upsert then drop the stale chunks, a failure leaves the previous ones searchable.
+            let points = TaskBmc::embed_points(mm, &[src]).await?;
+            let keep = points.iter().map(|point| point.id).collect();
+            mm.vs
+                .update_points(TaskBmc::COLLECTION_NAME, points)
+                .await?;
+            // The previous version of the story may have more chunks.
+            mm.vs
+                .delete_task_points_except(TaskBmc::COLLECTION_NAME, story_id, keep)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// One change per story, by latest entry id. The latest op wins, the attempts are the highest.
+fn coalesce(entries: Vec<OutboxEntry>) -> Vec<StoryChange> {
+    let mut changes: BTreeMap<i64, StoryChange> = BTreeMap::new();
+    for entry in entries {
+        match changes.get_mut(&entry.story_id) {
+            Some(change) => {
+                change.attempts = change.attempts.max(entry.attempts);
+                change.entry_count += 1;
+                if entry.id > change.last.id {
+                    change.last = entry;
+                }
+            }
+            None => {
+                let change = StoryChange {
+                    story_id: entry.story_id,
+                    attempts: entry.attempts,
+                    entry_count: 1,
+                    last: entry,
+                };
+                changes.insert(change.story_id, change);
+            }
+        }
+    }
+    let mut changes: Vec<StoryChange> = changes.into_values().collect();
+    changes.sort_by_key(|change| change.last.id);
+    changes
+}
+
+fn backoff_secs(attempts: i32) -> f64 {
+    2f64.powi(attempts).min(MAX_BACKOFF_SECS)
+}
+
+/// Spawn the worker applying the outbox to the vector store until the process exits.
+pub fn spawn_outbox_worker<E: Embedder, V: VectorBackend>(
+    mm: ModelManager<E, V>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match OutboxBmc::process_pending(mm.clone()).await {
+                Ok(0) => sleep(OUTBOX_POLL_INTERVAL).await,
+                Ok(handled) => debug!("{:<12} - handled {handled} entries", "OUTBOX"),
+                Err(ex) => {
+                    error!("{:<12} - process_pending failed: {ex:?}", "OUTBOX");
+                    sleep(OUTBOX_POLL_INTERVAL).await;
+                }
+            }
+        }
+    })
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::ctx::Ctx;
+    use crate::model::task::{TaskForCreate, TaskForUpdate};
+    use crate::model::MemVecStore;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder};
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    async fn pending_count(
+        mm: &ModelManager<HashingEmbedder, MemVecStore>,
+        story_id: i64,
+    ) -> Result<i64> {
+        let (count,): (i64,) =
+            sqlx::query_as("SELECT count(*) FROM story_outbox WHERE story_id = $1")
+                .bind(story_id)
+                .fetch_one(&mm.db)
+                .await?;
+        Ok(count)
+    }
+
+    fn entry(id: i64, story_id: i64, op: OutboxOp, attempts: i32) -> OutboxEntry {
+        OutboxEntry {
+            id,
+            story_id,
+            op: op.as_str().to_string(),
+            attempts,
+        }
+    }
+
+    #[test]
+    fn test_coalesce_latest_op_wins() {
+        let changes = coalesce(vec![
+            entry(1, 10, OutboxOp::Upsert, 3),
+            entry(2, 20, OutboxOp::Upsert, 0),
+            entry(3, 10, OutboxOp::Delete, 0),
+        ]);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].story_id, 20);
+        assert_eq!(changes[1].story_id, 10);
+        assert_eq!(changes[1].last.id, 3);
+        assert_eq!(changes[1].last.op, "delete");
+        assert_eq!(changes[1].attempts, 3);
+        assert_eq!(changes[1].entry_count, 2);
+    }
+
+    #[test]
+    fn test_backoff_secs_capped() {
+        assert_eq!(backoff_secs(1), 2.);
+        assert_eq!(backoff_secs(3), 8.);
+        assert_eq!(backoff_secs(30), MAX_BACKOFF_SECS);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_process_all_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Create, the point shows up once the outbox is processed.
+        let task = TaskForCreate {
+            story: "This is a story".to_string(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        assert_eq!(pending_count(&mm, id).await?, 1);
+        OutboxBmc::process_all(mm.clone()).await?;
+        assert_eq!(pending_count(&mm, id).await?, 0);
+        let embs = mm
+            .vs
+            .get_point_embeddings(TaskBmc::COLLECTION_NAME, vec![TaskBmc::point_id(id, 0)])
+            .await?;
+        assert_eq!(embs.len(), 1);
+
+        // -- Delete, the point goes away once the outbox is processed.
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        let embs = mm
+            .vs
+            .get_point_embeddings(TaskBmc::COLLECTION_NAME, vec![TaskBmc::point_id(id, 0)])
+            .await?;
+        assert!(embs.is_empty());
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_process_all_update_drops_stale_chunks() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let chunk_count = |mm: ModelManager<HashingEmbedder, MemVecStore>, id: i64| async move {
+            let points = mm.vs.scroll_points(TaskBmc::COLLECTION_NAME).await?;
+            Ok::<_, anyhow::Error>(points.iter().filter(|(_, p)| p.task_id == id).count())
+        };
+
+        // -- A long story, then a short one.
+        let task = TaskForCreate {
+            story: "The frogs sing in the pond at night. ".repeat(200),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        assert!(chunk_count(mm.clone(), id).await? > 1);
+        let task = TaskForUpdate {
+            id,
+            story: "The frogs sleep.".to_string(),
+            status: None,
+            tags: None,
+        };
+        TaskBmc::update(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        assert_eq!(chunk_count(mm.clone(), id).await?, 1);
+
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_claim_one_worker_per_story() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        let task = TaskForCreate {
+            story: "This is a story".to_string(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+
+        // -- Claimed by a first worker, a second one gets nothing, even for a newer entry.
+        let changes = OutboxBmc::claim(&mm).await?;
+        assert!(changes.iter().any(|change| change.story_id == id));
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        let changes = OutboxBmc::claim(&mm).await?;
+        assert!(!changes.iter().any(|change| change.story_id == id));
+
+        // -- Released, the delete wins.
+        sqlx::query("UPDATE story_outbox SET claimed_until = NULL WHERE story_id = $1")
+            .bind(id)
+            .execute(&mm.db)
+            .await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        assert_eq!(pending_count(&mm, id).await?, 0);
+        let embs = mm
+            .vs
+            .get_point_embeddings(TaskBmc::COLLECTION_NAME, vec![TaskBmc::point_id(id, 0)])
+            .await?;
+        assert!(embs.is_empty());
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_process_all_gives_up() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        let story_id = 999_999;
+
+        // -- Fails on its last attempt.
+        sqlx::query(
+            "INSERT INTO story_outbox (story_id, owner_id, op, attempts) VALUES ($1, 0, 'bogus', $2)",
+        )
+        .bind(story_id)
+        .bind(MAX_ATTEMPTS - 1)
+        .execute(&mm.db)
+        .await?;
+        assert_eq!(OutboxBmc::process_all(mm.clone()).await?, 1);
+        let (dead, last_error): (bool, Option<String>) = sqlx::query_as(
+            "SELECT dead_at IS NOT NULL, last_error FROM story_outbox WHERE story_id = $1",
+        )
+        .bind(story_id)
+        .fetch_one(&mm.db)
+        .await?;
+        assert!(dead);
+        assert!(last_error.unwrap_or_default().contains("OutboxInvalidOp"));
+
+        // -- Not retried, even once due.
+        sqlx::query("UPDATE story_outbox SET next_attempt_at = now() WHERE story_id = $1")
+            .bind(story_id)
+            .execute(&mm.db)
+            .await?;
+        assert_eq!(OutboxBmc::process_all(mm.clone()).await?, 0);
+
+        sqlx::query("DELETE FROM story_outbox WHERE story_id = $1")
+            .bind(story_id)
+            .execute(&mm.db)
+            .await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
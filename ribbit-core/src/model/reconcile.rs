@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use serde::Serialize;
+use tracing::info;
+
+use crate::model::error::Result;
+use crate::model::outbox::OutboxBmc;
+use crate::model::task::{TaskBmc, VsBmc};
+use crate::model::{ModelManager, VectorBackend};
+
+use super::embedder::Embedder;
+
+///
+/// Reconcile: diff the story table against the task collection.
+///     - missing points (row without any chunk point) are re-embedded through the outbox
+///     - orphan points (point of a task without row) are deleted
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    pub story_count: usize,
+    pub point_count: usize,
+    /// Story ids without any point.
+    pub missing_points: Vec<i64>,
+    /// Point ids whose task has no story.
+    pub orphan_points: Vec<u64>,
+    pub repaired: bool,
+}
+
+impl ReconcileReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_points.is_empty() && self.orphan_points.is_empty()
+    }
+}
+
+/// Diff the story table and the task collection, and fix the differences if `repair`.
+///
+/// NOTE: Changes still in the outbox show up as differences until the worker applied them.
+pub async fn reconcile(
+    mm: ModelManager<impl Embedder, impl VectorBackend>,
+    repair: bool,
+) -> Result<ReconcileReport> {
+    // -- Collect both id sets.
+    let points = mm.vs.scroll_points(TaskBmc::COLLECTION_NAME).await?;
+    let point_task_ids: HashSet<i64> = points.iter().map(|(_, p)| p.task_id).collect();
+    let mut story_ids: HashSet<i64> = HashSet::new();
+    let mut rows = sqlx::query_as::<_, (i64,)>("SELECT id FROM story").fetch(&mm.db);
+    while let Some((id,)) = rows.try_next().await? {
+        story_ids.insert(id);
+    }
+    drop(rows);
+
+    // -- Diff.
+    let mut missing_points: Vec<i64> = story_ids
+        .iter()
+        .filter(|id| !point_task_ids.contains(id))
+        .copied()
+        .collect();
+    let mut orphan_points: Vec<u64> = points
+        .iter()
+        .filter(|(_, payload)| !story_ids.contains(&payload.task_id))
+        .map(|(id, _)| *id)
+        .collect();
+    missing_points.sort();
+    orphan_points.sort();
+
+    let mut report = ReconcileReport {
+        story_count: story_ids.len(),
+        point_count: points.len(),
+        missing_points,
+        orphan_points,
+        repaired: false,
+    };
+    info!(
+        "{:<12} - {} stories, {} points, {} missing, {} orphans",
+        "RECONCILE",
+        report.story_count,
+        report.point_count,
+        report.missing_points.len(),
+        report.orphan_points.len()
+    );
+
+    // -- Repair.
+    if repair && !report.is_consistent() {
+        if !report.orphan_points.is_empty() {
+            mm.vs
+                .delete_points(TaskBmc::COLLECTION_NAME, report.orphan_points.clone())
+                .await?;
+        }
+        if !report.missing_points.is_empty() {
+            sqlx::query(
+                "
+                INSERT INTO story_outbox (story_id, owner_id, op)
+                SELECT id, owner_id, 'upsert' FROM story WHERE id = ANY($1)
+                ",
+            )
+            .bind(&report.missing_points)
+            .execute(&mm.db)
+            .await?;
+            OutboxBmc::process_all(mm.clone()).await?;
+        }
+        report.repaired = true;
+    }
+
+    Ok(report)
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::ctx::Ctx;
+    use crate::model::store::{NewPoint, PointPayload};
+    use crate::model::task::TaskForCreate;
+    use crate::model::MemVecStore;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder};
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    #[serial]
+    #[tokio::test]
+    async fn test_reconcile_repair_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let task = TaskForCreate {
+            story: "This is a story".to_string(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Break both stores.
+        mm.vs
+            .delete_task_points(TaskBmc::COLLECTION_NAME, vec![id])
+            .await?;
+        let orphan_id = TaskBmc::point_id(999_999, 0);
+        let orphan = NewPoint {
+            id: orphan_id,
+            embedding: mm.embedder.embed("orphan").await?,
+            payload: PointPayload {
+                owner_id: 0,
+                task_id: 999_999,
+                chunk_len: 6,
+                ..Default::default()
+            },
+        };
+        mm.vs
+            .update_points(TaskBmc::COLLECTION_NAME, vec![orphan])
+            .await?;
+
+        // -- Report only.
+        let report = reconcile(mm.clone(), false).await?;
+        assert!(report.missing_points.contains(&id));
+        assert!(report.orphan_points.contains(&orphan_id));
+        assert!(!report.repaired);
+
+        // -- Repair, then check again.
+        let report = reconcile(mm.clone(), true).await?;
+        assert!(report.repaired);
+        let report = reconcile(mm.clone(), false).await?;
+        assert!(report.is_consistent(), "{report:?}");
+
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
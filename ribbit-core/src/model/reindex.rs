@@ -0,0 +1,206 @@
+//! Reindex: re-embed the task collection without downtime, e.g. after a change of embedder model.
+//!     - a versioned collection `task_v{N}` is created with the configured dim/distance
+//!     - it is backfilled from the story table, a batch of stories per embedding request
+//!     - the alias `task` is swapped to it in one step, then the previous collection is dropped
+//!     - the stories created or updated since the backfill started are re-embedded after the swap,
+//!       then a reconcile drops the points of the stories deleted meanwhile
+//!
+//! Searches and writes go through the alias, they use the previous collection until the swap.
+//!
+//! NOTE: Only the Qdrant backend has aliases. `task` is an alias of `task_v1` from its creation
+//!       (see `VectorBackend::create_aliased_collection`), a plain `task` collection left by an
+//!       older version is refused with `CollectionNotAliased`, it is never deleted.
+//! NOTE: The catch up starts `CATCH_UP_MARGIN` before the backfill, for the story transactions
+//!       open when it started (their `updated_at` is the time of their start).
+
+use serde::Serialize;
+use sqlx::types::time::OffsetDateTime;
+use tracing::info;
+
+use crate::config::{config, QdrantCollection};
+use crate::model::error::Result;
+use crate::model::reconcile::{reconcile, ReconcileReport};
+use crate::model::store;
+use crate::model::task::{TaskBmc, VsBmc};
+use crate::model::{ModelManager, VectorBackend};
+
+use super::embedder::Embedder;
+
+const REINDEX_BATCH_SIZE: i64 = 64;
+/// Postgres interval.
+const CATCH_UP_MARGIN: &str = "1 minute";
+
+#[derive(Debug, Serialize)]
+pub struct ReindexReport {
+    /// The collection behind the alias, before and after.
+    pub previous: String,
+    pub current: String,
+    pub story_count: usize,
+    pub point_count: usize,
+    /// The catch up after the swap, stories changed during the backfill then orphan points.
+    pub caught_up: usize,
+    pub reconcile: ReconcileReport,
+}
+
+pub async fn reindex(mm: ModelManager<impl Embedder, impl VectorBackend>) -> Result<ReindexReport> {
+    let alias = TaskBmc::COLLECTION_NAME;
+    let clct = config()
+        .qdrant
+        .collections
+        .iter()
+        .find(|c| c.name == alias)
+        .ok_or_else(|| store::Error::CollectionNotFound(alias.to_string()))?;
+    let previous = mm
+        .vs
+        .alias_target(alias)
+        .await?
+        .ok_or_else(|| store::Error::CollectionNotAliased(alias.to_string()))?;
+    let target = QdrantCollection {
+        name: next_version(alias, &previous),
+        ..clct.clone()
+    };
+    info!("{:<12} - {alias}: {previous} -> {}", "REINDEX", target.name);
+
+    // -- Backfill, a leftover of a failed run is wiped first.
+    let (started_at,): (OffsetDateTime,) = sqlx::query_as("SELECT now() - $1::TEXT::INTERVAL")
+        .bind(CATCH_UP_MARGIN)
+        .fetch_one(&mm.db)
+        .await?;
+    mm.vs.reset_collection(&target).await?;
+    let (story_count, point_count) = backfill(&mm, &target.name, None).await?;
+    info!(
+        "{:<12} - {} backfilled, {story_count} stories, {point_count} points",
+        "REINDEX", target.name
+    );
+
+    // -- Swap, drop the previous collection, then catch up.
+    mm.vs.swap_alias(alias, &target.name).await?;
+    mm.vs.delete_collection(&previous).await?;
+    let (caught_up, _) = backfill(&mm, &target.name, Some(started_at)).await?;
+    let reconcile = reconcile(mm, true).await?;
+
+    Ok(ReindexReport {
+        previous,
+        current: target.name,
+        story_count,
+        point_count,
+        caught_up,
+        reconcile,
+    })
+}
+
+/// Embed the stories (changed since `changed_since`) into `collection`.
+/// Returns the story and point counts.
+async fn backfill(
+    mm: &ModelManager<impl Embedder, impl VectorBackend>,
+    collection: &str,
+    changed_since: Option<OffsetDateTime>,
+) -> Result<(usize, usize)> {
+    let (mut story_count, mut point_count) = (0, 0);
+    let mut after_id = 0;
+    loop {
+        let srcs = TaskBmc::sources_after(mm, after_id, changed_since, REINDEX_BATCH_SIZE).await?;
+        let Some(last) = srcs.last() else {
+            break;
+        };
+        after_id = last.id;
+        let points = TaskBmc::embed_points(mm, &srcs).await?;
+        let keeps: Vec<(i64, Vec<u64>)> = srcs
+            .iter()
+            .map(|src| {
+                let ids = points
+                    .iter()
+                    .filter(|point| point.payload.task_id == src.id)
+                    .map(|point| point.id);
+                (src.id, ids.collect())
+            })
+            .collect();
+        story_count += srcs.len();
+        point_count += points.len();
+        mm.vs.update_points(collection, points).await?;
+        if changed_since.is_some() {
+            // The backfilled version of the story may have more chunks.
+            for (task_id, keep) in keeps {
+                mm.vs
+                    .delete_task_points_except(collection, task_id, keep)
+                    .await?;
+            }
+        }
+    }
+    Ok((story_count, point_count))
+}
+
+/// `task_v1` -> `task_v2`, `task_v2` -> `task_v3`.
+fn next_version(alias: &str, current: &str) -> String {
+    let version = current
+        .strip_prefix(alias)
+        .and_then(|rest| rest.strip_prefix("_v"))
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(1);
+    format!("{alias}_v{}", version + 1)
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::ctx::Ctx;
+    use crate::model::outbox::OutboxBmc;
+    use crate::model::store::{PointFilter, SearchOptions};
+    use crate::model::task::TaskForCreate;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder};
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    #[test]
+    fn test_next_version() {
+        assert_eq!(next_version("task", "task_v1"), "task_v2");
+        assert_eq!(next_version("task", "task_v2"), "task_v3");
+        assert_eq!(next_version("task", "task_v41"), "task_v42");
+        assert_eq!(next_version("task", "task_vx"), "task_v2");
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_reindex_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder>::from_config().await?;
+        let task = TaskForCreate {
+            story: "Reindex the frog pond".to_string(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Twice, each run drops the collection it swapped from.
+        for _ in 0..2 {
+            let report = reindex(mm.clone()).await?;
+            assert!(report.story_count >= 1);
+            // -- Created within the margin, so caught up too.
+            assert!(report.caught_up >= 1, "{report:?}");
+            assert!(report.reconcile.is_consistent(), "{report:?}");
+            let target = mm.vs.alias_target(TaskBmc::COLLECTION_NAME).await?;
+            assert_eq!(target.as_deref(), Some(report.current.as_str()));
+            let collections = mm.vs.list_collections().await?;
+            assert!(!collections.contains(&report.previous));
+
+            let hits = TaskBmc::search(
+                ctx.clone(),
+                mm.clone(),
+                "frog pond",
+                &PointFilter::default(),
+                &SearchOptions::new(10),
+            )
+            .await?;
+            assert!(hits.iter().any(|hit| hit.task.id == id));
+        }
+
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -0,0 +1,151 @@
+// region:   --- Modules
+
+use sqlx::PgConnection;
+
+pub use super::error::Result;
+use super::vec_backend::{
+    CollectionSchema, NewPoint, PointFilter, PointPayload, SearchOptions, VectorBackend,
+};
+use super::vec_store::{EmbeddingState, NoScore, Point, WithEmbedding, WithScore};
+use super::{Db, MemVecStore, PgVecStore, VecStore};
+use crate::config::{config, QdrantCollection, VectorBackendKind};
+
+// endregion: --- Modules
+
+/// The `VectorBackend` selected by `vector_backend` in the config.
+#[derive(Clone)]
+pub enum AnyVecStore {
+    Qdrant(VecStore),
+    PgVector(PgVecStore),
+    Memory(MemVecStore),
+}
+
+/// Forward a call to the selected backend.
+macro_rules! dispatch {
+    ($self:ident, $vs:ident => $call:expr) => {
+        match $self {
+            AnyVecStore::Qdrant($vs) => $call,
+            AnyVecStore::PgVector($vs) => $call,
+            AnyVecStore::Memory($vs) => $call,
+        }
+    };
+}
+
+impl VectorBackend for AnyVecStore {
+    async fn from_config() -> Result<Self> {
+        let vs = match config().vector_backend {
+            VectorBackendKind::Qdrant => Self::Qdrant(VecStore::from_config().await?),
+            VectorBackendKind::PgVector => Self::PgVector(PgVecStore::from_config().await?),
+            VectorBackendKind::Memory => Self::Memory(MemVecStore::from_config().await?),
+        };
+        Ok(vs)
+    }
+
+    async fn from_config_with_db(db: Db) -> Result<Self> {
+        let vs = match config().vector_backend {
+            VectorBackendKind::PgVector => {
+                Self::PgVector(PgVecStore::from_config_with_db(db).await?)
+            }
+            _ => Self::from_config().await?,
+        };
+        Ok(vs)
+    }
+
+    async fn create_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        dispatch!(self, vs => vs.create_collection(clct).await)
+    }
+
+    async fn create_aliased_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        dispatch!(self, vs => vs.create_aliased_collection(clct).await)
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        dispatch!(self, vs => vs.list_collections().await)
+    }
+
+    async fn delete_collection(&self, name: &str) -> Result<()> {
+        dispatch!(self, vs => vs.delete_collection(name).await)
+    }
+
+    async fn reset_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        dispatch!(self, vs => vs.reset_collection(clct).await)
+    }
+
+    async fn collection_schema(&self, name: &str) -> Result<Option<CollectionSchema>> {
+        dispatch!(self, vs => vs.collection_schema(name).await)
+    }
+
+    async fn alias_target(&self, name: &str) -> Result<Option<String>> {
+        dispatch!(self, vs => vs.alias_target(name).await)
+    }
+
+    async fn swap_alias(&self, name: &str, collection: &str) -> Result<()> {
+        dispatch!(self, vs => vs.swap_alias(name, collection).await)
+    }
+
+    async fn update_points(&self, name: &str, points: Vec<NewPoint>) -> Result<()> {
+        dispatch!(self, vs => vs.update_points(name, points).await)
+    }
+
+    async fn get_point_embeddings(
+        &self,
+        name: &str,
+        ids: Vec<u64>,
+    ) -> Result<Vec<Point<NoScore, WithEmbedding>>> {
+        dispatch!(self, vs => vs.get_point_embeddings(name, ids).await)
+    }
+
+    async fn seach_points<E: EmbeddingState>(
+        &self,
+        name: &str,
+        owner_id: i64,
+        embedding: Vec<f32>,
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> Result<Vec<Point<WithScore, E>>> {
+        dispatch!(self, vs => vs.seach_points::<E>(name, owner_id, embedding, filter, opts).await)
+    }
+
+    async fn scroll_points(&self, name: &str) -> Result<Vec<(u64, PointPayload)>> {
+        dispatch!(self, vs => vs.scroll_points(name).await)
+    }
+
+    async fn delete_points(&self, name: &str, ids: Vec<u64>) -> Result<()> {
+        dispatch!(self, vs => vs.delete_points(name, ids).await)
+    }
+
+    async fn delete_task_points(&self, name: &str, task_ids: Vec<i64>) -> Result<()> {
+        dispatch!(self, vs => vs.delete_task_points(name, task_ids).await)
+    }
+
+    async fn delete_task_points_except(
+        &self,
+        name: &str,
+        task_id: i64,
+        keep: Vec<u64>,
+    ) -> Result<()> {
+        dispatch!(self, vs => vs.delete_task_points_except(name, task_id, keep).await)
+    }
+
+    fn is_transactional(&self) -> bool {
+        dispatch!(self, vs => vs.is_transactional())
+    }
+
+    async fn update_points_tx(
+        &self,
+        conn: &mut PgConnection,
+        name: &str,
+        points: Vec<NewPoint>,
+    ) -> Result<()> {
+        dispatch!(self, vs => vs.update_points_tx(conn, name, points).await)
+    }
+
+    async fn delete_task_points_tx(
+        &self,
+        conn: &mut PgConnection,
+        name: &str,
+        task_ids: Vec<i64>,
+    ) -> Result<()> {
+        dispatch!(self, vs => vs.delete_task_points_tx(conn, name, task_ids).await)
+    }
+}
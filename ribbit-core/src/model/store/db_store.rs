@@ -21,6 +21,16 @@ pub async fn new_db_pool() -> Result<Db> {
         .map_err(|ex| Error::FailToCreatePool(ex.to_string()))
 }
 
+/// Pool that connects on first use, for the places that can't await.
+pub fn new_lazy_db_pool() -> Result<Db> {
+    let max_connections = if cfg!(test) { 1 } else { 5 };
+
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect_lazy(&config().database.db_url)
+        .map_err(|ex| Error::FailToCreatePool(ex.to_string()))
+}
+
 // NOTE 1) This is not an ideal situation; however, with sqlx 0.7.1, when executing `cargo test`, some tests that use sqlx fail at a
 //         rather low level (in the tokio scheduler). It appears to be a low-level thread/async issue, as removing/adding
 //         tests causes different tests to fail. The cause remains uncertain, but setting max_connections to 1 resolves the issue.
@@ -1,15 +1,40 @@
+use super::vec_backend::CollectionSchema;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     InvalidDistanceName(String),
     FailToCreatePool(String),
+    CollectionNotFound(String),
+    VecDimMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    CollectionSchemaMismatch {
+        name: String,
+        expected: CollectionSchema,
+        actual: CollectionSchema,
+    },
+    InvalidCollectionName(String),
+    BackendNotTransactional,
+    BackendNoAliases,
+    CollectionNotAliased(String),
+    InvalidFilter(String),
+    PointVectorMissing(u64),
     // External
     QdrantUrlNotFound(String),
     QdrantFetchError(String),
     QdrantUpdateError(String),
     QdrantDeleteError(String),
     QdrantCreateError(String),
+    QdrantInvalidPayload(String),
+    QdrantInvalidCollection(String),
+    PgVecFetchError(String),
+    PgVecUpdateError(String),
+    PgVecDeleteError(String),
+    PgVecCreateError(String),
 }
 
 // region:    --- Error Boilerplate
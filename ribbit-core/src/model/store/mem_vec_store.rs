@@ -0,0 +1,588 @@
+// region:   --- Modules
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+pub use super::error::{Error, Result};
+use super::vec_backend::{
+    normalize, CollectionSchema, Embedding, NewPoint, PointFilter, PointPayload, SearchOptions,
+    VecDistance, VectorBackend, VectorsShape,
+};
+use super::vec_store::{EmbeddingState, NoScore, Point, WithEmbedding, WithScore};
+use crate::config::{config, QdrantCollection};
+
+// endregion: --- Modules
+
+/// In process `VectorBackend` with exact (brute force) scoring, for tests and CI.
+/// `SearchOptions::exact` and `hnsw_ef` have nothing to tune here.
+#[derive(Clone, Default)]
+pub struct MemVecStore {
+    collections: Arc<RwLock<HashMap<String, MemCollection>>>,
+}
+
+struct MemCollection {
+    dim: u64,
+    distance: VecDistance,
+    points: BTreeMap<u64, MemPoint>,
+}
+
+struct MemPoint {
+    payload: PointPayload,
+    embedding: Embedding,
+}
+
+impl MemVecStore {
+    fn with_collection<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut MemCollection) -> Result<T>,
+    ) -> Result<T> {
+        let mut collections = self.collections.write().unwrap();
+        let clct = collections
+            .get_mut(name)
+            .ok_or_else(|| Error::CollectionNotFound(name.to_string()))?;
+        f(clct)
+    }
+}
+
+impl VectorBackend for MemVecStore {
+    async fn from_config() -> Result<Self> {
+        let vs = MemVecStore::default();
+        vs.init_collections(&config().qdrant.collections).await?;
+        Ok(vs)
+    }
+
+    async fn create_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        let distance = VecDistance::from_name(&clct.distance)?;
+        if self.collection_schema(&clct.name).await?.is_some() {
+            return self.check_collection(clct).await;
+        }
+        let mut collections = self.collections.write().unwrap();
+        collections
+            .entry(clct.name.clone())
+            .or_insert_with(|| MemCollection {
+                dim: clct.dim,
+                distance,
+                points: BTreeMap::new(),
+            });
+        Ok(())
+    }
+
+    async fn collection_schema(&self, name: &str) -> Result<Option<CollectionSchema>> {
+        let collections = self.collections.read().unwrap();
+        Ok(collections.get(name).map(|clct| CollectionSchema {
+            dim: clct.dim,
+            distance: clct.distance,
+            shape: VectorsShape::Single,
+        }))
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let collections = self.collections.read().unwrap();
+        Ok(collections.keys().cloned().collect())
+    }
+
+    async fn delete_collection(&self, name: &str) -> Result<()> {
+        self.collections.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    async fn update_points(&self, name: &str, points: Vec<NewPoint>) -> Result<()> {
+        self.with_collection(name, |clct| {
+            // -- Check all the dims first, so a bad batch writes nothing.
+            if let Some(point) = points.iter().find(|p| p.embedding.len() as u64 != clct.dim) {
+                return Err(Error::VecDimMismatch {
+                    expected: clct.dim,
+                    actual: point.embedding.len() as u64,
+                });
+            }
+            for NewPoint {
+                id,
+                mut embedding,
+                payload,
+            } in points
+            {
+                // Like Qdrant, cosine collections store normalized vectors.
+                if clct.distance == VecDistance::Cosine {
+                    normalize(&mut embedding);
+                }
+                clct.points.insert(id, MemPoint { payload, embedding });
+            }
+            Ok(())
+        })
+    }
+
+    async fn get_point_embeddings(
+        &self,
+        name: &str,
+        ids: Vec<u64>,
+    ) -> Result<Vec<Point<NoScore, WithEmbedding>>> {
+        self.with_collection(name, |clct| {
+            Ok(ids
+                .iter()
+                .filter_map(|id| {
+                    clct.points.get(id).map(|p| Point {
+                        id: *id,
+                        score: NoScore,
+                        embedding: WithEmbedding(p.embedding.clone()),
+                        payload: Some(p.payload.clone()),
+                    })
+                })
+                .collect())
+        })
+    }
+
+    async fn seach_points<E: EmbeddingState>(
+        &self,
+        name: &str,
+        owner_id: i64,
+        mut embedding: Vec<f32>,
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> Result<Vec<Point<WithScore, E>>> {
+        filter.validate()?;
+        self.with_collection(name, |clct| {
+            if embedding.len() as u64 != clct.dim {
+                return Err(Error::VecDimMismatch {
+                    expected: clct.dim,
+                    actual: embedding.len() as u64,
+                });
+            }
+            if clct.distance == VecDistance::Cosine {
+                normalize(&mut embedding);
+            }
+            let mut scored: Vec<(u64, f32, &MemPoint)> = clct
+                .points
+                .iter()
+                .filter(|(_, p)| p.payload.owner_id == owner_id && filter.matches(&p.payload))
+                .map(|(id, p)| (*id, clct.distance.score(&embedding, &p.embedding), p))
+                .filter(|(_, score, _)| match opts.score_threshold {
+                    Some(threshold) => clct.distance.within_threshold(*score, threshold),
+                    None => true,
+                })
+                .collect();
+            scored.sort_by(|a, b| {
+                let ord = a.1.total_cmp(&b.1);
+                if clct.distance.higher_is_better() {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            });
+            scored
+                .into_iter()
+                .skip(opts.offset as usize)
+                .take(opts.limit as usize)
+                .map(|(id, score, p)| {
+                    let vector = E::WITH_VECTORS.then(|| p.embedding.clone());
+                    let payload = opts.with_payload.then(|| p.payload.clone());
+                    Point::hit(id, score, vector, payload)
+                })
+                .collect()
+        })
+    }
+
+    async fn scroll_points(&self, name: &str) -> Result<Vec<(u64, PointPayload)>> {
+        self.with_collection(name, |clct| {
+            Ok(clct
+                .points
+                .iter()
+                .map(|(id, p)| (*id, p.payload.clone()))
+                .collect())
+        })
+    }
+
+    async fn delete_points(&self, name: &str, ids: Vec<u64>) -> Result<()> {
+        self.with_collection(name, |clct| {
+            for id in ids {
+                clct.points.remove(&id);
+            }
+            Ok(())
+        })
+    }
+
+    async fn delete_task_points(&self, name: &str, task_ids: Vec<i64>) -> Result<()> {
+        self.with_collection(name, |clct| {
+            clct.points
+                .retain(|_, p| !task_ids.contains(&p.payload.task_id));
+            Ok(())
+        })
+    }
+
+    async fn delete_task_points_except(
+        &self,
+        name: &str,
+        task_id: i64,
+        keep: Vec<u64>,
+    ) -> Result<()> {
+        self.with_collection(name, |clct| {
+            clct.points
+                .retain(|id, p| p.payload.task_id != task_id || keep.contains(id));
+            Ok(())
+        })
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use crate::config::CollectionRole;
+    use crate::model::store::{FieldCondition, NoEmbedding, PayloadField};
+    use anyhow::Result;
+
+    fn clct(distance: &str) -> QdrantCollection {
+        QdrantCollection {
+            name: "mem_test".to_string(),
+            dim: 2,
+            distance: distance.to_string(),
+            role: CollectionRole::Test,
+            reset_on_start: false,
+        }
+    }
+
+    fn point(id: u64, owner_id: i64, embedding: Vec<f32>) -> NewPoint {
+        NewPoint {
+            id,
+            embedding,
+            payload: PointPayload {
+                owner_id,
+                task_id: id as i64 / 10,
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn new_store(distance: &str) -> Result<MemVecStore> {
+        let vs = MemVecStore::default();
+        vs.create_collection(&clct(distance)).await?;
+        vs.update_points(
+            "mem_test",
+            vec![
+                point(1, 0, vec![1., 0.]),
+                point(2, 0, vec![0., 2.]),
+                point(3, 0, vec![3., 3.]),
+            ],
+        )
+        .await?;
+        Ok(vs)
+    }
+
+    fn ids(hits: &[Point<WithScore, NoEmbedding>]) -> Vec<u64> {
+        hits.iter().map(|h| h.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_cosine_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let hits = vs
+            .seach_points(
+                "mem_test",
+                0,
+                vec![2., 0.1],
+                &PointFilter::default(),
+                &SearchOptions::new(3),
+            )
+            .await?;
+        assert_eq!(ids(&hits), vec![1, 3, 2]);
+        assert!((hits[0].score() - 0.99875).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_dot_ok() -> Result<()> {
+        let vs = new_store("Dot").await?;
+        let hits = vs
+            .seach_points(
+                "mem_test",
+                0,
+                vec![1., 1.],
+                &PointFilter::default(),
+                &SearchOptions::new(2),
+            )
+            .await?;
+        assert_eq!(ids(&hits), vec![3, 2]);
+        assert_eq!(hits[0].score(), 6.);
+        assert_eq!(hits[1].score(), 2.);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_euclid_ok() -> Result<()> {
+        let vs = new_store("Euclid").await?;
+        let hits = vs
+            .seach_points(
+                "mem_test",
+                0,
+                vec![1., 1.],
+                &PointFilter::default(),
+                &SearchOptions::new(3),
+            )
+            .await?;
+        assert_eq!(ids(&hits), vec![1, 2, 3]);
+        assert!((hits[0].score() - 1.).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_options_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let all = PointFilter::default();
+        let query = vec![2., 0.1];
+
+        // -- Threshold, point 2 is almost orthogonal.
+        let opts = SearchOptions {
+            score_threshold: Some(0.5),
+            ..SearchOptions::new(3)
+        };
+        let hits = vs
+            .seach_points("mem_test", 0, query.clone(), &all, &opts)
+            .await?;
+        assert_eq!(ids(&hits), vec![1, 3]);
+
+        // -- Pages of one.
+        let mut paged = Vec::new();
+        for offset in 0..4 {
+            let opts = SearchOptions {
+                offset,
+                ..SearchOptions::new(1)
+            };
+            paged.extend(ids(&vs
+                .seach_points("mem_test", 0, query.clone(), &all, &opts)
+                .await?));
+        }
+        assert_eq!(paged, vec![1, 3, 2]);
+
+        // -- Vectors without payload.
+        let opts = SearchOptions {
+            with_payload: false,
+            ..SearchOptions::new(1)
+        };
+        let hits = vs
+            .seach_points::<WithEmbedding>("mem_test", 0, query, &all, &opts)
+            .await?;
+        assert!(hits[0].payload.is_none());
+        assert_eq!(hits[0].embedding(), &vec![1., 0.]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_euclid_threshold_ok() -> Result<()> {
+        let vs = new_store("Euclid").await?;
+        let opts = SearchOptions {
+            score_threshold: Some(1.5),
+            ..SearchOptions::new(3)
+        };
+        let hits = vs
+            .seach_points("mem_test", 0, vec![1., 1.], &PointFilter::default(), &opts)
+            .await?;
+        assert_eq!(ids(&hits), vec![1, 2]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_other_owner_empty() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let hits = vs
+            .seach_points::<NoEmbedding>(
+                "mem_test",
+                1000,
+                vec![1., 1.],
+                &PointFilter::default(),
+                &SearchOptions::new(3),
+            )
+            .await?;
+        assert!(hits.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_filter_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let mut p = point(4, 0, vec![1., 0.1]);
+        p.payload.tags = vec!["backend".to_string()];
+        p.payload.status = Some("open".to_string());
+        vs.update_points("mem_test", vec![p]).await?;
+
+        let filter = PointFilter::default()
+            .must(FieldCondition::Match(PayloadField::Status, "open".into()))
+            .must(FieldCondition::Match(PayloadField::Tags, "backend".into()));
+        let hits = vs
+            .seach_points("mem_test", 0, vec![1., 0.], &filter, &SearchOptions::new(3))
+            .await?;
+        assert_eq!(ids(&hits), vec![4]);
+
+        let filter = PointFilter::default().must_not(FieldCondition::MatchAny(
+            PayloadField::TaskId,
+            vec![0.into()],
+        ));
+        let hits = vs
+            .seach_points::<NoEmbedding>(
+                "mem_test",
+                0,
+                vec![1., 0.],
+                &filter,
+                &SearchOptions::new(3),
+            )
+            .await?;
+        assert!(hits.is_empty());
+
+        let filter = PointFilter::default().must(FieldCondition::Range(
+            PayloadField::Status,
+            Default::default(),
+        ));
+        let res = vs
+            .seach_points::<NoEmbedding>(
+                "mem_test",
+                0,
+                vec![1., 0.],
+                &filter,
+                &SearchOptions::new(3),
+            )
+            .await;
+        assert!(matches!(res, Err(Error::InvalidFilter(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_points_err_dim_mismatch() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let res = vs
+            .update_points("mem_test", vec![point(4, 0, vec![1.])])
+            .await;
+        assert!(
+            matches!(res, Err(Error::VecDimMismatch { .. })),
+            "Expected VecDimMismatch, got {:?}",
+            res
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_points_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        vs.delete_points("mem_test", vec![1, 2]).await?;
+        let ids: Vec<u64> = vs
+            .scroll_points("mem_test")
+            .await?
+            .into_iter()
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(ids, vec![3]);
+        let points = vs.get_point_embeddings("mem_test", vec![1, 3]).await?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].id, 3);
+        assert_eq!(points[0].payload.as_ref().map(|p| p.task_id), Some(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_task_points_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        vs.update_points(
+            "mem_test",
+            vec![point(10, 0, vec![1., 0.]), point(11, 0, vec![0., 1.])],
+        )
+        .await?;
+        vs.delete_task_points("mem_test", vec![1]).await?;
+        let ids: Vec<u64> = vs
+            .scroll_points("mem_test")
+            .await?
+            .into_iter()
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_task_points_except_ok() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        vs.update_points(
+            "mem_test",
+            vec![
+                point(10, 0, vec![1., 0.]),
+                point(11, 0, vec![0., 1.]),
+                point(12, 0, vec![1., 1.]),
+            ],
+        )
+        .await?;
+        vs.delete_task_points_except("mem_test", 1, vec![10, 11])
+            .await?;
+        let ids: Vec<u64> = vs
+            .scroll_points("mem_test")
+            .await?
+            .into_iter()
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 10, 11]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_collection_err_invalid_distance() {
+        let vs = MemVecStore::default();
+        let res = vs.create_collection(&clct("Manhatten")).await;
+        assert!(matches!(res, Err(Error::InvalidDistanceName(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_collection_err_schema_mismatch() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let res = vs
+            .create_collection(&QdrantCollection {
+                dim: 3,
+                ..clct("Cosine")
+            })
+            .await;
+        let Err(Error::CollectionSchemaMismatch {
+            expected, actual, ..
+        }) = res
+        else {
+            panic!("Expected CollectionSchemaMismatch, got {:?}", res);
+        };
+        assert_eq!((expected.dim, actual.dim), (3, 2));
+        assert_eq!(expected.shape, actual.shape);
+        vs.create_collection(&clct("Cosine")).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_collections_err_schema_mismatch() -> Result<()> {
+        let vs = new_store("Cosine").await?;
+        let changed = QdrantCollection {
+            dim: 3,
+            ..clct("Dot")
+        };
+        let res = vs.init_collections(&[changed]).await;
+        assert!(
+            matches!(res, Err(Error::CollectionSchemaMismatch { .. })),
+            "Expected CollectionSchemaMismatch, got {:?}",
+            res
+        );
+        // -- The points are kept.
+        assert_eq!(vs.scroll_points("mem_test").await?.len(), 3);
+        vs.init_collections(&[clct("Cosine")]).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_collections_reset_once() -> Result<()> {
+        let vs = MemVecStore::default();
+        let reset = QdrantCollection {
+            name: "mem_test_reset".to_string(),
+            reset_on_start: true,
+            ..clct("Cosine")
+        };
+        vs.init_collections(std::slice::from_ref(&reset)).await?;
+        vs.update_points("mem_test_reset", vec![point(10, 1, vec![1., 0.])])
+            .await?;
+
+        // -- Already reset for this backend, the points are kept.
+        vs.init_collections(&[reset]).await?;
+        assert_eq!(vs.scroll_points("mem_test_reset").await?.len(), 1);
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -1,12 +1,18 @@
 // region:   --- Modules
 
+mod any_vec_store;
 mod db_store;
 mod error;
+mod mem_vec_store;
+mod pg_vec_store;
+mod vec_backend;
 mod vec_store;
 
-use std::collections::HashMap;
-
-pub use self::error::{Error, Result};
+pub use self::error::Error;
 
+pub use self::any_vec_store::AnyVecStore;
 pub use self::db_store::*;
+pub use self::mem_vec_store::MemVecStore;
+pub use self::pg_vec_store::PgVecStore;
+pub use self::vec_backend::*;
 pub use self::vec_store::*;
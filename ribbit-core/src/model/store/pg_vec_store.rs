@@ -0,0 +1,842 @@
+// region:   --- Modules
+
+use pgvector::Vector;
+use sqlx::{FromRow, PgConnection};
+use tracing::debug;
+
+pub use super::error::{Error, Result};
+use super::vec_backend::{
+    CollectionSchema, FieldCondition, FieldValue, NewPoint, PayloadField, PointFilter,
+    PointPayload, SearchOptions, VecDistance, VectorBackend, VectorsShape,
+};
+use super::vec_store::{EmbeddingState, NoScore, Point, WithEmbedding, WithScore};
+use super::{new_db_pool, Db};
+use crate::config::{config, QdrantCollection};
+
+// endregion: --- Modules
+
+///
+/// PgVecStore: embeddings in a pgvector table per collection, next to the story table.
+///     - the collections (dim, distance) are registered in `vec_collection`
+///     - points of collection `task` live in `vec_task (id, owner_id, task_id, .., embedding)`
+///
+/// NOTE: The `vector` extension and `vec_collection` come from the `0004_pgvector` migration.
+#[derive(Clone)]
+pub struct PgVecStore {
+    db: Db,
+}
+
+impl PgVecStore {
+    async fn distance(&self, name: &str) -> Result<VecDistance> {
+        let (distance,): (String,) =
+            sqlx::query_as("SELECT distance FROM vec_collection WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| Error::PgVecFetchError(e.to_string()))?
+                .ok_or_else(|| Error::CollectionNotFound(name.to_string()))?;
+        VecDistance::from_name(&distance)
+    }
+}
+
+/// Table of the collection. The name is checked since it ends up in the sql.
+fn table_name(name: &str) -> Result<String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(format!("vec_{}", name.to_lowercase()))
+    } else {
+        Err(Error::InvalidCollectionName(name.to_string()))
+    }
+}
+
+/// `(score expression, order by expression, index operator class)` of the distance.
+/// The scores follow Qdrant: cosine similarity, inner product, euclidean distance.
+fn distance_sql(distance: VecDistance) -> (&'static str, &'static str, &'static str) {
+    match distance {
+        VecDistance::Cosine => (
+            "1 - (embedding <=> $1)",
+            "embedding <=> $1",
+            "vector_cosine_ops",
+        ),
+        VecDistance::Dot => ("-(embedding <#> $1)", "embedding <#> $1", "vector_ip_ops"),
+        VecDistance::Euclid => ("embedding <-> $1", "embedding <-> $1", "vector_l2_ops"),
+    }
+}
+
+// region:    --- Payload
+
+/// The payload columns, named as `PayloadField::key`.
+const PAYLOAD_COLUMNS: &str =
+    "owner_id, task_id, chunk_offset, chunk_len, tags, status, created_at";
+
+#[derive(FromRow)]
+struct PayloadRow {
+    owner_id: i64,
+    task_id: i64,
+    chunk_offset: i64,
+    chunk_len: i64,
+    tags: Vec<String>,
+    status: Option<String>,
+    created_at: Option<i64>,
+}
+
+impl From<PayloadRow> for PointPayload {
+    fn from(row: PayloadRow) -> Self {
+        PointPayload {
+            owner_id: row.owner_id,
+            task_id: row.task_id,
+            chunk_offset: row.chunk_offset,
+            chunk_len: row.chunk_len,
+            tags: row.tags,
+            status: row.status,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct PointRow {
+    id: i64,
+    #[sqlx(flatten)]
+    payload: PayloadRow,
+}
+
+#[derive(FromRow)]
+struct EmbeddingRow {
+    id: i64,
+    #[sqlx(flatten)]
+    payload: PayloadRow,
+    embedding: Vector,
+}
+
+#[derive(FromRow)]
+struct HitRow {
+    id: i64,
+    score: f32,
+    #[sqlx(flatten)]
+    payload: PayloadRow,
+    /// NULL unless `EmbeddingState::WITH_VECTORS`.
+    embedding: Option<Vector>,
+}
+
+// endregion: --- Payload
+
+// region:    --- Filter
+
+/// A value bound to a filter placeholder.
+enum FilterBind {
+    Integer(i64),
+    Keyword(String),
+    Integers(Vec<i64>),
+    Keywords(Vec<String>),
+    Float(f64),
+}
+
+/// `WHERE` clause of the filter, with its binds from placeholder `$first` on.
+/// The columns are `PayloadField::key`, so nothing from the caller ends up in the sql.
+///
+/// NOTE: Conditions on a NULL column are false (not NULL), as for a missing Qdrant key.
+fn filter_sql(filter: &PointFilter, first: usize) -> (String, Vec<FilterBind>) {
+    let mut binds = Vec::new();
+    let mut cond_sql = |cond: &FieldCondition| -> String {
+        let column = cond.field().key();
+        let mut push = |bind: FilterBind| {
+            binds.push(bind);
+            format!("${}", first + binds.len() - 1)
+        };
+        let sql = match cond {
+            FieldCondition::Match(PayloadField::Tags, value) => {
+                format!("{} = ANY(tags)", push(value_bind(value)))
+            }
+            FieldCondition::Match(_, value) => format!("{column} = {}", push(value_bind(value))),
+            FieldCondition::MatchAny(field, values) => {
+                let bind = if field.is_integer() {
+                    FilterBind::Integers(
+                        values
+                            .iter()
+                            .filter_map(|v| match v {
+                                FieldValue::Integer(v) => Some(*v),
+                                FieldValue::Keyword(_) => None,
+                            })
+                            .collect(),
+                    )
+                } else {
+                    FilterBind::Keywords(
+                        values
+                            .iter()
+                            .filter_map(|v| match v {
+                                FieldValue::Keyword(v) => Some(v.clone()),
+                                FieldValue::Integer(_) => None,
+                            })
+                            .collect(),
+                    )
+                };
+                match field {
+                    PayloadField::Tags => format!("tags && {}::TEXT[]", push(bind)),
+                    _ => format!("{column} = ANY({})", push(bind)),
+                }
+            }
+            FieldCondition::Range(_, range) => {
+                let bounds = [
+                    (">", range.gt),
+                    (">=", range.gte),
+                    ("<", range.lt),
+                    ("<=", range.lte),
+                ];
+                let mut parts: Vec<String> = bounds
+                    .into_iter()
+                    .filter_map(|(op, bound)| {
+                        bound.map(|b| {
+                            format!("{column} {op} {}::FLOAT8", push(FilterBind::Float(b)))
+                        })
+                    })
+                    .collect();
+                if parts.is_empty() {
+                    parts.push(format!("{column} IS NOT NULL"));
+                }
+                parts.join(" AND ")
+            }
+        };
+        format!("COALESCE({sql}, FALSE)")
+    };
+
+    let mut clauses: Vec<String> = filter.must.iter().map(&mut cond_sql).collect();
+    if !filter.should.is_empty() {
+        let should: Vec<String> = filter.should.iter().map(&mut cond_sql).collect();
+        clauses.push(format!("({})", should.join(" OR ")));
+    }
+    for cond in &filter.must_not {
+        clauses.push(format!("NOT {}", cond_sql(cond)));
+    }
+    if clauses.is_empty() {
+        clauses.push("TRUE".to_string());
+    }
+    (clauses.join(" AND "), binds)
+}
+
+fn value_bind(value: &FieldValue) -> FilterBind {
+    match value {
+        FieldValue::Integer(v) => FilterBind::Integer(*v),
+        FieldValue::Keyword(v) => FilterBind::Keyword(v.clone()),
+    }
+}
+
+// endregion: --- Filter
+
+async fn upsert_points(conn: &mut PgConnection, name: &str, points: Vec<NewPoint>) -> Result<()> {
+    let sql = format!(
+        "
+        INSERT INTO {} (id, {PAYLOAD_COLUMNS}, embedding)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (id) DO UPDATE SET
+            owner_id = EXCLUDED.owner_id, task_id = EXCLUDED.task_id,
+            chunk_offset = EXCLUDED.chunk_offset, chunk_len = EXCLUDED.chunk_len,
+            tags = EXCLUDED.tags, status = EXCLUDED.status, created_at = EXCLUDED.created_at,
+            embedding = EXCLUDED.embedding
+        ",
+        table_name(name)?
+    );
+    for point in points {
+        sqlx::query(&sql)
+            .bind(point.id as i64)
+            .bind(point.payload.owner_id)
+            .bind(point.payload.task_id)
+            .bind(point.payload.chunk_offset)
+            .bind(point.payload.chunk_len)
+            .bind(&point.payload.tags)
+            .bind(&point.payload.status)
+            .bind(point.payload.created_at)
+            .bind(Vector::from(point.embedding))
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| Error::PgVecUpdateError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+async fn remove_points(conn: &mut PgConnection, name: &str, ids: Vec<u64>) -> Result<()> {
+    let ids: Vec<i64> = ids.into_iter().map(|id| id as i64).collect();
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE id = ANY($1)",
+        table_name(name)?
+    ))
+    .bind(&ids)
+    .execute(conn)
+    .await
+    .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+    Ok(())
+}
+
+async fn remove_task_points(conn: &mut PgConnection, name: &str, task_ids: Vec<i64>) -> Result<()> {
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE task_id = ANY($1)",
+        table_name(name)?
+    ))
+    .bind(&task_ids)
+    .execute(conn)
+    .await
+    .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+    Ok(())
+}
+
+impl VectorBackend for PgVecStore {
+    async fn from_config() -> Result<Self> {
+        Self::from_config_with_db(new_db_pool().await?).await
+    }
+
+    async fn from_config_with_db(db: Db) -> Result<Self> {
+        let vs = PgVecStore { db };
+        vs.init_collections(&config().qdrant.collections).await?;
+        Ok(vs)
+    }
+
+    async fn create_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        if self.list_collections().await?.contains(&clct.name) {
+            debug!("pg collection {} already exists.", clct.name);
+            return self.check_collection(clct).await;
+        }
+        let table = table_name(&clct.name)?;
+        let distance = VecDistance::from_name(&clct.distance)?;
+        let (_, _, ops) = distance_sql(distance);
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| Error::PgVecCreateError(e.to_string()))?;
+        let sqls = [
+            format!(
+                "
+                CREATE TABLE {table} (
+                    id BIGINT PRIMARY KEY,
+                    owner_id BIGINT NOT NULL,
+                    task_id BIGINT NOT NULL,
+                    chunk_offset BIGINT NOT NULL,
+                    chunk_len BIGINT NOT NULL,
+                    tags TEXT[] NOT NULL DEFAULT '{{}}',
+                    status TEXT,
+                    created_at BIGINT,
+                    embedding vector({}) NOT NULL
+                )
+                ",
+                clct.dim
+            ),
+            format!("CREATE INDEX ON {table} (owner_id)"),
+            format!("CREATE INDEX ON {table} (task_id)"),
+            format!("CREATE INDEX ON {table} USING gin (tags)"),
+            format!("CREATE INDEX ON {table} USING hnsw (embedding {ops})"),
+        ];
+        for sql in sqls {
+            sqlx::query(&sql)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::PgVecCreateError(e.to_string()))?;
+        }
+        sqlx::query("INSERT INTO vec_collection (name, dim, distance) VALUES ($1, $2, $3)")
+            .bind(&clct.name)
+            .bind(clct.dim as i64)
+            .bind(&clct.distance)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::PgVecCreateError(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::PgVecCreateError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn collection_schema(&self, name: &str) -> Result<Option<CollectionSchema>> {
+        let row: Option<(i64, String)> =
+            sqlx::query_as("SELECT dim, distance FROM vec_collection WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        row.map(|(dim, distance)| {
+            Ok(CollectionSchema {
+                dim: dim as u64,
+                distance: VecDistance::from_name(&distance)?,
+                shape: VectorsShape::Single,
+            })
+        })
+        .transpose()
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM vec_collection")
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        Ok(names.into_iter().map(|(name,)| name).collect())
+    }
+
+    async fn delete_collection(&self, name: &str) -> Result<()> {
+        let table = table_name(name)?;
+        sqlx::query(&format!("DROP TABLE IF EXISTS {table}"))
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+        sqlx::query("DELETE FROM vec_collection WHERE name = $1")
+            .bind(name)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn update_points(&self, name: &str, points: Vec<NewPoint>) -> Result<()> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| Error::PgVecUpdateError(e.to_string()))?;
+        upsert_points(&mut tx, name, points).await?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::PgVecUpdateError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_point_embeddings(
+        &self,
+        name: &str,
+        ids: Vec<u64>,
+    ) -> Result<Vec<Point<NoScore, WithEmbedding>>> {
+        let ids: Vec<i64> = ids.into_iter().map(|id| id as i64).collect();
+        let rows: Vec<EmbeddingRow> = sqlx::query_as(&format!(
+            "
+            SELECT id, {PAYLOAD_COLUMNS}, embedding FROM {}
+            WHERE id = ANY($1)
+            ORDER BY array_position($1, id)
+            ",
+            table_name(name)?
+        ))
+        .bind(&ids)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Point {
+                id: row.id as u64,
+                score: NoScore,
+                embedding: WithEmbedding(row.embedding.to_vec()),
+                payload: Some(row.payload.into()),
+            })
+            .collect())
+    }
+
+    /// `exact` turns the index scans off and `hnsw_ef` sets `hnsw.ef_search`,
+    /// both for the transaction of the search only.
+    async fn seach_points<E: EmbeddingState>(
+        &self,
+        name: &str,
+        owner_id: i64,
+        embedding: Vec<f32>,
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> Result<Vec<Point<WithScore, E>>> {
+        filter.validate()?;
+        let distance = self.distance(name).await?;
+        let (score, order_by, _) = distance_sql(distance);
+        let threshold_cmp = if distance.higher_is_better() {
+            ">="
+        } else {
+            "<="
+        };
+        let embedding_col = if E::WITH_VECTORS {
+            "embedding"
+        } else {
+            "NULL::vector"
+        };
+        let (filter_sql, binds) = filter_sql(filter, 6);
+        let sql = format!(
+            "
+            SELECT id, ({score})::REAL AS score, {PAYLOAD_COLUMNS}, {embedding_col} AS embedding
+            FROM {}
+            WHERE owner_id = $2
+                AND ($4::REAL IS NULL OR ({score}) {threshold_cmp} $4)
+                AND {filter_sql}
+            ORDER BY {order_by}
+            LIMIT $3 OFFSET $5
+            ",
+            table_name(name)?
+        );
+        let mut query = sqlx::query_as::<_, HitRow>(&sql)
+            .bind(Vector::from(embedding))
+            .bind(owner_id)
+            .bind(opts.limit as i64)
+            .bind(opts.score_threshold)
+            .bind(opts.offset as i64);
+        for bind in binds {
+            query = match bind {
+                FilterBind::Integer(v) => query.bind(v),
+                FilterBind::Keyword(v) => query.bind(v),
+                FilterBind::Integers(v) => query.bind(v),
+                FilterBind::Keywords(v) => query.bind(v),
+                FilterBind::Float(v) => query.bind(v),
+            };
+        }
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        let mut settings = Vec::new();
+        if opts.exact {
+            settings.push("SET LOCAL enable_indexscan = off".to_string());
+        }
+        if let Some(ef) = opts.hnsw_ef {
+            settings.push(format!("SET LOCAL hnsw.ef_search = {ef}"));
+        }
+        for sql in settings {
+            sqlx::query(&sql)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        }
+        let rows = query
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let payload = opts.with_payload.then(|| row.payload.into());
+                let vector = row.embedding.map(|emb| emb.to_vec());
+                Point::hit(row.id as u64, row.score, vector, payload)
+            })
+            .collect()
+    }
+
+    async fn scroll_points(&self, name: &str) -> Result<Vec<(u64, PointPayload)>> {
+        let rows: Vec<PointRow> = sqlx::query_as(&format!(
+            "SELECT id, {PAYLOAD_COLUMNS} FROM {}",
+            table_name(name)?
+        ))
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| Error::PgVecFetchError(e.to_string()))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id as u64, row.payload.into()))
+            .collect())
+    }
+
+    async fn delete_points(&self, name: &str, ids: Vec<u64>) -> Result<()> {
+        let mut conn = self
+            .db
+            .acquire()
+            .await
+            .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+        remove_points(&mut conn, name, ids).await
+    }
+
+    async fn delete_task_points(&self, name: &str, task_ids: Vec<i64>) -> Result<()> {
+        let mut conn = self
+            .db
+            .acquire()
+            .await
+            .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+        remove_task_points(&mut conn, name, task_ids).await
+    }
+
+    async fn delete_task_points_except(
+        &self,
+        name: &str,
+        task_id: i64,
+        keep: Vec<u64>,
+    ) -> Result<()> {
+        let keep: Vec<i64> = keep.into_iter().map(|id| id as i64).collect();
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE task_id = $1 AND id <> ALL($2)",
+            table_name(name)?
+        ))
+        .bind(task_id)
+        .bind(&keep)
+        .execute(&self.db)
+        .await
+        .map_err(|e| Error::PgVecDeleteError(e.to_string()))?;
+        Ok(())
+    }
+
+    // -- Transactional writes
+    fn is_transactional(&self) -> bool {
+        true
+    }
+
+    async fn update_points_tx(
+        &self,
+        conn: &mut PgConnection,
+        name: &str,
+        points: Vec<NewPoint>,
+    ) -> Result<()> {
+        upsert_points(conn, name, points).await
+    }
+
+    async fn delete_task_points_tx(
+        &self,
+        conn: &mut PgConnection,
+        name: &str,
+        task_ids: Vec<i64>,
+    ) -> Result<()> {
+        remove_task_points(conn, name, task_ids).await
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::_dev_utils;
+    use crate::config::CollectionRole;
+
+    #[allow(unused)]
+    use super::*;
+    use crate::model::store::{Embedding, NoEmbedding, Range};
+    use anyhow::Result;
+    use serial_test::serial;
+
+    /// Point `id` of owner `owner_id`, chunk of task `id / 10`.
+    fn point(id: u64, owner_id: i64, embedding: Embedding) -> NewPoint {
+        NewPoint {
+            id,
+            embedding,
+            payload: PointPayload {
+                owner_id,
+                task_id: id as i64 / 10,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn clct() -> QdrantCollection {
+        QdrantCollection {
+            name: "pg_test".to_string(),
+            dim: 2,
+            distance: "Cosine".to_string(),
+            role: CollectionRole::Test,
+            reset_on_start: false,
+        }
+    }
+
+    #[test]
+    fn test_table_name_err_invalid() {
+        assert_eq!(table_name("task").unwrap(), "vec_task");
+        assert!(matches!(
+            table_name("task; DROP TABLE story"),
+            Err(Error::InvalidCollectionName(_))
+        ));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let vs = PgVecStore::from_config().await?;
+        vs.reset_collection(&clct()).await?;
+        vs.update_points(
+            "pg_test",
+            vec![
+                point(10, 0, vec![1., 0.]),
+                point(11, 0, vec![0., 1.]),
+                point(20, 0, vec![1., 1.]),
+                point(30, 1000, vec![1., 0.]),
+            ],
+        )
+        .await?;
+
+        let hits = vs
+            .seach_points::<NoEmbedding>(
+                "pg_test",
+                0,
+                vec![1., 0.1],
+                &PointFilter::default(),
+                &SearchOptions::new(3),
+            )
+            .await?;
+        let ids: Vec<u64> = hits.iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![10, 20, 11]);
+        assert!(hits[0].score() > hits[1].score());
+        assert_eq!(hits[1].payload.as_ref().unwrap().task_id, 2);
+
+        vs.delete_points("pg_test", vec![20]).await?;
+        vs.delete_task_points("pg_test", vec![1]).await?;
+        let ids: Vec<u64> = vs
+            .scroll_points("pg_test")
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![30]);
+        vs.delete_collection("pg_test").await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_sql() {
+        let (sql, binds) = filter_sql(&PointFilter::default(), 4);
+        assert_eq!(sql, "TRUE");
+        assert!(binds.is_empty());
+
+        let filter = PointFilter::default()
+            .must(FieldCondition::Match(PayloadField::Tags, "backend".into()))
+            .should(FieldCondition::Match(PayloadField::Status, "open".into()))
+            .should(FieldCondition::MatchAny(
+                PayloadField::TaskId,
+                vec![1.into(), 2.into()],
+            ))
+            .must_not(FieldCondition::Range(
+                PayloadField::CreatedAt,
+                Range {
+                    gte: Some(10.),
+                    lt: Some(20.),
+                    ..Default::default()
+                },
+            ));
+        let (sql, binds) = filter_sql(&filter, 4);
+        assert_eq!(
+            sql,
+            "COALESCE($4 = ANY(tags), FALSE) \
+             AND (COALESCE(status = $5, FALSE) OR COALESCE(task_id = ANY($6), FALSE)) \
+             AND NOT COALESCE(created_at >= $7::FLOAT8 AND created_at < $8::FLOAT8, FALSE)"
+        );
+        assert_eq!(binds.len(), 5);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_filter_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let vs = PgVecStore::from_config().await?;
+        vs.reset_collection(&clct()).await?;
+        let mut open = point(10, 0, vec![1., 0.]);
+        open.payload.tags = vec!["backend".to_string()];
+        open.payload.status = Some("open".to_string());
+        let mut done = point(20, 0, vec![1., 0.]);
+        done.payload.tags = vec!["backend".to_string()];
+        done.payload.status = Some("done".to_string());
+        let untagged = point(30, 0, vec![1., 0.]);
+        vs.update_points("pg_test", vec![open, done, untagged])
+            .await?;
+
+        let filter = PointFilter::default()
+            .must(FieldCondition::Match(PayloadField::Tags, "backend".into()))
+            .must_not(FieldCondition::Match(PayloadField::Status, "done".into()));
+        let hits = vs
+            .seach_points::<NoEmbedding>(
+                "pg_test",
+                0,
+                vec![1., 0.],
+                &filter,
+                &SearchOptions::new(10),
+            )
+            .await?;
+        let ids: Vec<u64> = hits.iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![10]);
+        assert_eq!(
+            hits[0].payload.as_ref().unwrap().status.as_deref(),
+            Some("open")
+        );
+
+        // -- A NULL status is not "done".
+        let filter = PointFilter::default()
+            .must_not(FieldCondition::Match(PayloadField::Status, "done".into()));
+        let hits = vs
+            .seach_points::<NoEmbedding>(
+                "pg_test",
+                0,
+                vec![1., 0.],
+                &filter,
+                &SearchOptions::new(10),
+            )
+            .await?;
+        let mut ids: Vec<u64> = hits.iter().map(|h| h.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![10, 30]);
+
+        vs.delete_collection("pg_test").await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_options_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let vs = PgVecStore::from_config().await?;
+        vs.reset_collection(&clct()).await?;
+        vs.update_points(
+            "pg_test",
+            vec![
+                point(10, 0, vec![1., 0.]),
+                point(20, 0, vec![1., 1.]),
+                point(30, 0, vec![0., 1.]),
+            ],
+        )
+        .await?;
+        let all = PointFilter::default();
+
+        // -- Exact, above the threshold, paged by one.
+        let mut ids = Vec::new();
+        for offset in 0..3 {
+            let opts = SearchOptions {
+                offset,
+                score_threshold: Some(0.5),
+                exact: true,
+                hnsw_ef: Some(64),
+                ..SearchOptions::new(1)
+            };
+            let hits = vs
+                .seach_points::<NoEmbedding>("pg_test", 0, vec![1., 0.1], &all, &opts)
+                .await?;
+            ids.extend(hits.iter().map(|h| h.id));
+        }
+        assert_eq!(ids, vec![10, 20]);
+
+        // -- Vectors without payload.
+        let opts = SearchOptions {
+            with_payload: false,
+            ..SearchOptions::new(1)
+        };
+        let hits = vs
+            .seach_points::<WithEmbedding>("pg_test", 0, vec![1., 0.], &all, &opts)
+            .await?;
+        assert!(hits[0].payload.is_none());
+        assert_eq!(hits[0].embedding(), &vec![1., 0.]);
+
+        vs.delete_collection("pg_test").await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_update_points_tx_rollback() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let vs = PgVecStore::from_config().await?;
+        vs.reset_collection(&clct()).await?;
+
+        let mut tx = vs.db.begin().await?;
+        vs.update_points_tx(&mut tx, "pg_test", vec![point(1, 0, vec![1., 0.])])
+            .await?;
+        tx.rollback().await?;
+        assert!(vs
+            .get_point_embeddings("pg_test", vec![1])
+            .await?
+            .is_empty());
+
+        let mut tx = vs.db.begin().await?;
+        vs.update_points_tx(&mut tx, "pg_test", vec![point(1, 0, vec![1., 0.])])
+            .await?;
+        tx.commit().await?;
+        let points = vs.get_point_embeddings("pg_test", vec![1]).await?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].embedding(), &vec![1., 0.]);
+        assert_eq!(points[0].payload.as_ref().map(|p| p.task_id), Some(0));
+
+        vs.delete_collection("pg_test").await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
@@ -0,0 +1,595 @@
+use std::any::TypeId;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Mutex;
+
+use sqlx::PgConnection;
+use tracing::info;
+
+pub use super::error::{Error, Result};
+use super::vec_store::{EmbeddingState, NoScore, Point, WithEmbedding, WithScore};
+use super::Db;
+use crate::config::QdrantCollection;
+
+pub type Embedding = Vec<f32>;
+
+/// Payload of a point: its owner, the chunk of the task it embeds and the task fields
+/// the searches filter on (see `PointFilter`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PointPayload {
+    pub owner_id: i64,
+    pub task_id: i64,
+    /// Char offset and length of the chunk in the story.
+    pub chunk_offset: i64,
+    pub chunk_len: i64,
+    pub tags: Vec<String>,
+    pub status: Option<String>,
+    /// Unix timestamp (secs).
+    pub created_at: Option<i64>,
+}
+
+/// A point to upsert.
+#[derive(Debug, Clone)]
+pub struct NewPoint {
+    pub id: u64,
+    pub embedding: Embedding,
+    pub payload: PointPayload,
+}
+
+/// Options of `VectorBackend::seach_points`. Whether the vectors come back is set by
+/// the `EmbeddingState` of the returned points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    pub limit: u64,
+    /// Skip the first `offset` hits, to page through the results.
+    pub offset: u64,
+    /// Drop the hits scoring below (Euclid: above) the threshold.
+    pub score_threshold: Option<f32>,
+    /// Brute force instead of the ANN index, e.g. to measure its recall.
+    pub exact: bool,
+    /// Size of the HNSW candidate list, the index default if unset.
+    pub hnsw_ef: Option<u64>,
+    pub with_payload: bool,
+}
+
+impl SearchOptions {
+    pub fn new(limit: u64) -> Self {
+        SearchOptions {
+            limit,
+            offset: 0,
+            score_threshold: None,
+            exact: false,
+            hnsw_ef: None,
+            with_payload: true,
+        }
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+/// Dim, distance and vectors shape of a collection, as configured or as found in the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionSchema {
+    pub dim: u64,
+    pub distance: VecDistance,
+    pub shape: VectorsShape,
+}
+
+impl CollectionSchema {
+    pub fn from_config(clct: &QdrantCollection) -> Result<Self> {
+        Ok(CollectionSchema {
+            dim: clct.dim,
+            distance: VecDistance::from_name(&clct.distance)?,
+            shape: VectorsShape::Single,
+        })
+    }
+}
+
+/// How the vectors of a point are declared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VectorsShape {
+    /// One unnamed vector, the only shape the app reads and writes.
+    Single,
+    /// Named vectors (sorted), e.g. a collection created by another tool.
+    /// The dim and distance of the schema are the ones of the first name.
+    Named(Vec<String>),
+}
+
+/// Storage of the task embeddings. `VecStore` (Qdrant) is the production backend,
+/// `PgVecStore` keeps them in postgres, `MemVecStore` keeps everything in process for tests.
+///
+/// NOTE: A task has one point per chunk, searches only return the points of `owner_id`.
+pub trait VectorBackend: Clone + Send + Sync + 'static {
+    fn from_config() -> impl Future<Output = Result<Self>> + Send;
+
+    /// Same as `from_config`, a postgres backend uses `db` rather than its own pool.
+    fn from_config_with_db(_db: Db) -> impl Future<Output = Result<Self>> + Send {
+        Self::from_config()
+    }
+
+    // -- Collections
+    /// An existing collection is kept, it must match `clct` (see `check_collection`).
+    fn create_collection(&self, clct: &QdrantCollection)
+        -> impl Future<Output = Result<()>> + Send;
+    /// `create_collection` where `clct.name` is an alias of `{name}_v1` on the backends
+    /// with aliases, so that `reindex` never has to replace a live collection.
+    fn create_aliased_collection(
+        &self,
+        clct: &QdrantCollection,
+    ) -> impl Future<Output = Result<()>> + Send {
+        self.create_collection(clct)
+    }
+    fn list_collections(&self) -> impl Future<Output = Result<Vec<String>>> + Send;
+    fn delete_collection(&self, name: &str) -> impl Future<Output = Result<()>> + Send;
+    fn reset_collection(&self, clct: &QdrantCollection) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            self.delete_collection(&clct.name).await?;
+            self.create_collection(clct).await?;
+            Ok(())
+        }
+    }
+
+    /// `None` if the collection does not exist.
+    fn collection_schema(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<CollectionSchema>>> + Send;
+    /// `CollectionSchemaMismatch` if the collection does not match its config.
+    fn check_collection(&self, clct: &QdrantCollection) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let expected = CollectionSchema::from_config(clct)?;
+            let actual = self
+                .collection_schema(&clct.name)
+                .await?
+                .ok_or_else(|| Error::CollectionNotFound(clct.name.clone()))?;
+            if actual != expected {
+                return Err(Error::CollectionSchemaMismatch {
+                    name: clct.name.clone(),
+                    expected,
+                    actual,
+                });
+            }
+            Ok(())
+        }
+    }
+    /// Create the missing collections (behind an alias, see `create_aliased_collection`),
+    /// an existing collection is checked against its config but never deleted because of it.
+    ///
+    /// NOTE: The `reset_on_start` collections (dev only, see `Qdrant::validate`) are reset on
+    ///       the first call of the process for each backend type, not on every `from_config`.
+    fn init_collections(
+        &self,
+        clcts: &[QdrantCollection],
+    ) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            static RESET: Mutex<BTreeSet<(TypeId, String)>> = Mutex::new(BTreeSet::new());
+            for clct in clcts {
+                let on_start = clct.reset_on_start
+                    && RESET
+                        .lock()
+                        .unwrap()
+                        .insert((TypeId::of::<Self>(), clct.name.clone()));
+                if on_start {
+                    info!("{:<12} - reset collection {}", "FOR-DEV-ONLY", clct.name);
+                    // -- The collection behind the alias, the alias is kept.
+                    match self.alias_target(&clct.name).await? {
+                        Some(name) => {
+                            let target = QdrantCollection {
+                                name,
+                                ..clct.clone()
+                            };
+                            self.reset_collection(&target).await?;
+                        }
+                        None => {
+                            self.delete_collection(&clct.name).await?;
+                            self.create_aliased_collection(clct).await?;
+                        }
+                    }
+                } else {
+                    self.create_aliased_collection(clct).await?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // -- Aliases
+    /// The collection behind the alias `name`, `None` if `name` is not an alias.
+    fn alias_target(&self, _name: &str) -> impl Future<Output = Result<Option<String>>> + Send {
+        async { Ok(None) }
+    }
+    /// Point the alias `name` to `collection` in one step, see `reindex`.
+    fn swap_alias(
+        &self,
+        _name: &str,
+        _collection: &str,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Err(Error::BackendNoAliases) }
+    }
+
+    // -- Points
+    fn update_points(
+        &self,
+        name: &str,
+        points: Vec<NewPoint>,
+    ) -> impl Future<Output = Result<()>> + Send;
+    /// Missing ids are skipped, the points come with their payload.
+    fn get_point_embeddings(
+        &self,
+        name: &str,
+        ids: Vec<u64>,
+    ) -> impl Future<Output = Result<Vec<Point<NoScore, WithEmbedding>>>> + Send;
+    /// Best match first, among the points of `owner_id` matching the `filter`.
+    /// `E = WithEmbedding` fetches the vectors too.
+    fn seach_points<E: EmbeddingState>(
+        &self,
+        name: &str,
+        owner_id: i64,
+        embedding: Vec<f32>,
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> impl Future<Output = Result<Vec<Point<WithScore, E>>>> + Send;
+    /// All the points of the collection, as `(id, payload)`.
+    fn scroll_points(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Vec<(u64, PointPayload)>>> + Send;
+    fn delete_points(&self, name: &str, ids: Vec<u64>) -> impl Future<Output = Result<()>> + Send;
+    /// Delete all the chunk points of the tasks.
+    fn delete_task_points(
+        &self,
+        name: &str,
+        task_ids: Vec<i64>,
+    ) -> impl Future<Output = Result<()>> + Send;
+    /// Delete the chunk points of the task but the `keep` ones, e.g. the chunks past the end
+    /// of a shorter new version of the story.
+    fn delete_task_points_except(
+        &self,
+        name: &str,
+        task_id: i64,
+        keep: Vec<u64>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    // -- Transactional writes
+    /// Whether the points can be written in the transaction of the story change
+    /// (`*_tx`), instead of through the outbox.
+    fn is_transactional(&self) -> bool {
+        false
+    }
+    fn update_points_tx(
+        &self,
+        _conn: &mut PgConnection,
+        _name: &str,
+        _points: Vec<NewPoint>,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Err(Error::BackendNotTransactional) }
+    }
+    fn delete_task_points_tx(
+        &self,
+        _conn: &mut PgConnection,
+        _name: &str,
+        _task_ids: Vec<i64>,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async { Err(Error::BackendNotTransactional) }
+    }
+}
+
+// region:    --- Filter
+
+/// The payload fields a `PointFilter` can test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadField {
+    OwnerId,
+    TaskId,
+    ChunkOffset,
+    ChunkLen,
+    Tags,
+    Status,
+    CreatedAt,
+}
+
+impl PayloadField {
+    /// Qdrant payload key, also the pgvector column.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::OwnerId => "owner_id",
+            Self::TaskId => "task_id",
+            Self::ChunkOffset => "chunk_offset",
+            Self::ChunkLen => "chunk_len",
+            Self::Tags => "tags",
+            Self::Status => "status",
+            Self::CreatedAt => "created_at",
+        }
+    }
+
+    /// Integer fields, the others are keywords.
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, Self::Tags | Self::Status)
+    }
+
+    /// The values of the field in the payload, several for `Tags`, none if unset.
+    fn values(&self, payload: &PointPayload) -> Vec<FieldValue> {
+        match self {
+            Self::OwnerId => vec![payload.owner_id.into()],
+            Self::TaskId => vec![payload.task_id.into()],
+            Self::ChunkOffset => vec![payload.chunk_offset.into()],
+            Self::ChunkLen => vec![payload.chunk_len.into()],
+            Self::Tags => payload.tags.iter().map(|tag| tag.as_str().into()).collect(),
+            Self::Status => payload.status.iter().map(|s| s.as_str().into()).collect(),
+            Self::CreatedAt => payload.created_at.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Integer(i64),
+    Keyword(String),
+}
+
+impl From<i64> for FieldValue {
+    fn from(v: i64) -> Self {
+        Self::Integer(v)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(v: &str) -> Self {
+        Self::Keyword(v.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(v: String) -> Self {
+        Self::Keyword(v)
+    }
+}
+
+/// Bounds of a `FieldCondition::Range`, unset bounds are open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Range {
+    pub gt: Option<f64>,
+    pub gte: Option<f64>,
+    pub lt: Option<f64>,
+    pub lte: Option<f64>,
+}
+
+impl Range {
+    fn contains(&self, v: f64) -> bool {
+        self.gt.into_iter().all(|b| v > b)
+            && self.gte.into_iter().all(|b| v >= b)
+            && self.lt.into_iter().all(|b| v < b)
+            && self.lte.into_iter().all(|b| v <= b)
+    }
+}
+
+/// A condition on one field. As in Qdrant, a field with several values (`Tags`)
+/// matches if any of its values does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldCondition {
+    Match(PayloadField, FieldValue),
+    /// Matches any of the values.
+    MatchAny(PayloadField, Vec<FieldValue>),
+    /// Integer fields only.
+    Range(PayloadField, Range),
+}
+
+impl FieldCondition {
+    pub fn field(&self) -> PayloadField {
+        match self {
+            Self::Match(field, _) | Self::MatchAny(field, _) | Self::Range(field, _) => *field,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let field = self.field();
+        let values = match self {
+            Self::Match(_, value) => std::slice::from_ref(value),
+            Self::MatchAny(_, values) => values.as_slice(),
+            Self::Range(..) if field.is_integer() => &[],
+            Self::Range(..) => return Err(Error::InvalidFilter(format!("range on {field:?}"))),
+        };
+        for value in values {
+            if matches!(value, FieldValue::Integer(_)) != field.is_integer() {
+                return Err(Error::InvalidFilter(format!("{value:?} for {field:?}")));
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, payload: &PointPayload) -> bool {
+        let values = self.field().values(payload);
+        match self {
+            Self::Match(_, value) => values.contains(value),
+            Self::MatchAny(_, any) => values.iter().any(|v| any.contains(v)),
+            Self::Range(_, range) => values.iter().any(|v| match v {
+                FieldValue::Integer(v) => range.contains(*v as f64),
+                FieldValue::Keyword(_) => false,
+            }),
+        }
+    }
+}
+
+/// Filter on the payload of the points: all of `must`, at least one of `should` (if any)
+/// and none of `must_not`. The default filter matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointFilter {
+    pub must: Vec<FieldCondition>,
+    pub should: Vec<FieldCondition>,
+    pub must_not: Vec<FieldCondition>,
+}
+
+impl PointFilter {
+    pub fn must(mut self, cond: FieldCondition) -> Self {
+        self.must.push(cond);
+        self
+    }
+
+    pub fn should(mut self, cond: FieldCondition) -> Self {
+        self.should.push(cond);
+        self
+    }
+
+    pub fn must_not(mut self, cond: FieldCondition) -> Self {
+        self.must_not.push(cond);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.must.is_empty() && self.should.is_empty() && self.must_not.is_empty()
+    }
+
+    /// Values must have the type of their field, ranges are on integer fields only.
+    pub fn validate(&self) -> Result<()> {
+        self.must
+            .iter()
+            .chain(&self.should)
+            .chain(&self.must_not)
+            .try_for_each(FieldCondition::validate)
+    }
+
+    pub fn matches(&self, payload: &PointPayload) -> bool {
+        self.must.iter().all(|c| c.matches(payload))
+            && (self.should.is_empty() || self.should.iter().any(|c| c.matches(payload)))
+            && !self.must_not.iter().any(|c| c.matches(payload))
+    }
+}
+
+// endregion: --- Filter
+
+// region:    --- Distance
+
+/// The distances of `QdrantCollection.distance`, named as Qdrant names them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VecDistance {
+    Cosine,
+    Dot,
+    Euclid,
+}
+
+impl VecDistance {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "Cosine" => Ok(Self::Cosine),
+            "Dot" => Ok(Self::Dot),
+            "Euclid" => Ok(Self::Euclid),
+            _ => Err(Error::InvalidDistanceName(name.to_string())),
+        }
+    }
+
+    /// Score as Qdrant reports it: similarity for Cosine/Dot, distance for Euclid.
+    ///
+    /// NOTE: For Cosine, the vectors are expected to be normalized already.
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine | Self::Dot => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+            Self::Euclid => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    /// Whether a higher score is a better match.
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(self, Self::Euclid)
+    }
+
+    /// Whether `score` passes the `SearchOptions::score_threshold`.
+    pub fn within_threshold(&self, score: f32, threshold: f32) -> bool {
+        if self.higher_is_better() {
+            score >= threshold
+        } else {
+            score <= threshold
+        }
+    }
+}
+
+// endregion: --- Distance
+
+/// L2 normalize in place, a zero vector is left untouched.
+pub fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0. {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    fn payload() -> PointPayload {
+        PointPayload {
+            owner_id: 1000,
+            task_id: 1,
+            tags: vec!["backend".to_string(), "api".to_string()],
+            status: Some("open".to_string()),
+            created_at: Some(1_700_000_000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let p = payload();
+        let open = FieldCondition::Match(PayloadField::Status, "open".into());
+        let backend = FieldCondition::Match(PayloadField::Tags, "backend".into());
+        let frontend = FieldCondition::Match(PayloadField::Tags, "frontend".into());
+        let recent = FieldCondition::Range(
+            PayloadField::CreatedAt,
+            Range {
+                gte: Some(1_600_000_000.),
+                ..Default::default()
+            },
+        );
+
+        assert!(PointFilter::default().matches(&p));
+        let f = PointFilter::default()
+            .must(open.clone())
+            .must(backend.clone());
+        assert!(f.matches(&p));
+        assert!(!f.must(frontend.clone()).matches(&p));
+        let f = PointFilter::default()
+            .should(frontend.clone())
+            .should(recent.clone());
+        assert!(f.matches(&p));
+        assert!(!PointFilter::default().must_not(open).matches(&p));
+        let any = FieldCondition::MatchAny(PayloadField::Tags, vec!["ui".into(), "api".into()]);
+        assert!(PointFilter::default().must(any).matches(&p));
+
+        // -- An unset field matches nothing.
+        let p = PointPayload::default();
+        assert!(!PointFilter::default().must(recent).matches(&p));
+        assert!(PointFilter::default().must_not(backend).matches(&p));
+    }
+
+    #[test]
+    fn test_filter_validate_err() {
+        let ok = PointFilter::default()
+            .must(FieldCondition::Match(PayloadField::TaskId, 1.into()))
+            .must(FieldCondition::Range(
+                PayloadField::CreatedAt,
+                Range::default(),
+            ));
+        assert!(ok.validate().is_ok());
+
+        let bad_value = PointFilter::default().must(FieldCondition::Match(
+            PayloadField::Status,
+            FieldValue::Integer(1),
+        ));
+        assert!(matches!(bad_value.validate(), Err(Error::InvalidFilter(_))));
+        let bad_range = PointFilter::default()
+            .must_not(FieldCondition::Range(PayloadField::Tags, Range::default()));
+        assert!(matches!(bad_range.validate(), Err(Error::InvalidFilter(_))));
+    }
+}
+// endregion: --- Test
@@ -1,71 +1,130 @@
 // region:   --- Modules
 
 use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::value::Kind;
 use qdrant_client::qdrant::vectors::VectorsOptions;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::u64;
-use tokio::sync::Mutex;
 
 pub use super::error::{Error, Result};
+use super::vec_backend::{
+    CollectionSchema, Embedding, FieldCondition, FieldValue, NewPoint, PayloadField, PointFilter,
+    PointPayload, SearchOptions, VecDistance, VectorBackend, VectorsShape,
+};
 use crate::config::{config, QdrantCollection};
 use qdrant_client::prelude::QdrantClient;
+use qdrant_client::qdrant::alias_operations::Action as AliasAction;
 use qdrant_client::qdrant::vectors_config::Config;
 use qdrant_client::qdrant::{
-    CreateCollection, Distance, GetResponse, PointId, PointStruct, SearchPoints, Value, Vector,
-    VectorParams, Vectors, VectorsConfig,
+    AliasOperations, ChangeAliases, Condition, CreateAlias, CreateCollection, DeleteAlias,
+    Distance, FieldType, Filter, GetResponse, ListValue, PointId, PointStruct,
+    Range as QdrantRange, ScrollPoints, SearchParams, SearchPoints, Value, VectorParams, Vectors,
+    VectorsConfig,
 };
-use tokio::sync::OnceCell;
-use tracing::{debug, info};
+use tracing::debug;
 
 // endregion: --- Modules
 
+/// The client is shared without a lock, its gRPC channel multiplexes the concurrent calls.
 #[derive(Clone)]
 pub struct VecStore {
-    qc: Arc<Mutex<QdrantClient>>,
+    qc: Arc<QdrantClient>,
 }
 
-type Embedding = Vec<f32>;
+/// The payload keys are `PayloadField::key`, every search is filtered on the owner.
+use PayloadField::{ChunkLen, ChunkOffset, CreatedAt, OwnerId, Status, Tags, TaskId};
+
+/// Payload indexes of the collections.
+const INDEXED_FIELDS: [(PayloadField, FieldType); 5] = [
+    (OwnerId, FieldType::Integer),
+    (TaskId, FieldType::Integer),
+    (Tags, FieldType::Keyword),
+    (Status, FieldType::Keyword),
+    (CreatedAt, FieldType::Integer),
+];
+
+const SCROLL_PAGE_SIZE: u32 = 256;
 
 // region:   --- point states
 
 pub trait ScoreState {}
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct NoScore;
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct WithScore(pub f32);
 
 impl ScoreState for NoScore {}
 impl ScoreState for WithScore {}
 
-pub trait EmbeddingState {}
+/// The searches only fetch the vectors for `WithEmbedding` points.
+pub trait EmbeddingState: Sized + Send + 'static {
+    const WITH_VECTORS: bool;
+    /// `None` if the state needs a vector the backend did not return.
+    fn from_vector(vector: Option<Embedding>) -> Option<Self>;
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct NoEmbedding;
+#[derive(Debug, Clone, PartialEq)]
 pub struct WithEmbedding(pub Embedding);
-impl EmbeddingState for NoEmbedding {}
-impl EmbeddingState for WithEmbedding {}
 
-// pub struct Point<S: ScoreState, E: EmbeddingState> {
-//     id: u64,
-//     score: S,
-//     embedding: E,
-// }
+impl EmbeddingState for NoEmbedding {
+    const WITH_VECTORS: bool = false;
+    fn from_vector(_vector: Option<Embedding>) -> Option<Self> {
+        Some(NoEmbedding)
+    }
+}
+impl EmbeddingState for WithEmbedding {
+    const WITH_VECTORS: bool = true;
+    fn from_vector(vector: Option<Embedding>) -> Option<Self> {
+        vector.map(WithEmbedding)
+    }
+}
+
+/// A stored point, its type tells whether it has a score and an embedding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point<S: ScoreState, E: EmbeddingState> {
+    pub id: u64,
+    pub score: S,
+    pub embedding: E,
+    /// `None` if not asked, see `SearchOptions::with_payload`.
+    pub payload: Option<PointPayload>,
+}
+
+impl<E: EmbeddingState> Point<WithScore, E> {
+    /// Search hit, fails if `E` needs the vector and the backend did not return it.
+    pub(super) fn hit(
+        id: u64,
+        score: f32,
+        vector: Option<Embedding>,
+        payload: Option<PointPayload>,
+    ) -> Result<Self> {
+        Ok(Point {
+            id,
+            score: WithScore(score),
+            embedding: E::from_vector(vector).ok_or(Error::PointVectorMissing(id))?,
+            payload,
+        })
+    }
+
+    pub fn score(&self) -> f32 {
+        self.score.0
+    }
+}
+
+impl<S: ScoreState> Point<S, WithEmbedding> {
+    pub fn embedding(&self) -> &Embedding {
+        &self.embedding.0
+    }
+
+    pub fn into_embedding(self) -> Embedding {
+        self.embedding.0
+    }
+}
 
 // endregion: --- point states
 
-impl VecStore {
-    // pub async fn from_config() -> Result<Self> {
-    //     static STORE: OnceCell<VecStore> = OnceCell::const_new();
-    //     let vs = STORE
-    //         .get_or_init(|| async {
-    //             println!("Initializing VecStore");
-    //             Self::_from_config().await.unwrap()
-    //         })
-    //         .await;
-    //     // FIXME
-    //     // vs.clear_collection(config().qdrant.collections.first().unwrap()) // first collection is for dev
-    //     //     .await?;
-    //     Ok(vs.clone())
-    // }
-
-    pub async fn from_config() -> Result<Self> {
+impl VectorBackend for VecStore {
+    async fn from_config() -> Result<Self> {
         let qc = QdrantClient::from_url(&config().qdrant.url)
             .build()
             .map_err(|e| {
@@ -74,45 +133,84 @@ impl VecStore {
                     e
                 ))
             })?;
-        let vs = VecStore {
-            qc: Arc::new(Mutex::new(qc)),
-        };
-
-        for clct in &config().qdrant.collections {
-            vs.create_collection(clct).await?;
-        }
-        vs.reset_collection(config().qdrant.collections.first().unwrap()) // first collection is for dev
-            .await?;
+        let vs = VecStore { qc: Arc::new(qc) };
+        vs.init_collections(&config().qdrant.collections).await?;
         Ok(vs)
     }
 
-    pub async fn create_collection(&self, clct: &QdrantCollection) -> Result<()> {
-        if self.list_collections().await?.contains(&clct.name) {
+    async fn create_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        if self.collection_schema(&clct.name).await?.is_some() {
             debug!("qd collection {} already exists.", clct.name);
-            return Ok(());
+            return self.check_collection(clct).await;
+        }
+        self.qc
+            .create_collection(&CreateCollection {
+                collection_name: clct.name.clone(),
+                vectors_config: Some(VectorsConfig {
+                    config: Some(Config::Params(VectorParams {
+                        size: clct.dim,
+                        distance: Distance::from_str_name(&clct.distance)
+                            .ok_or(Error::InvalidDistanceName(clct.distance.clone()))?
+                            .into(),
+                        ..Default::default()
+                    })),
+                }),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| Error::QdrantCreateError(format!("Failed to create collection: {}", e)))?;
+        for (field, field_type) in INDEXED_FIELDS {
+            let key = field.key();
+            self.qc
+                .create_field_index(&clct.name, key, field_type, None, None)
+                .await
+                .map_err(|e| {
+                    Error::QdrantCreateError(format!("Failed to create {key} index: {}", e))
+                })?;
         }
-        let qc = self.qc.lock().await;
-        qc.create_collection(&CreateCollection {
-            collection_name: clct.name.clone(),
-            vectors_config: Some(VectorsConfig {
-                config: Some(Config::Params(VectorParams {
-                    size: clct.dim,
-                    distance: Distance::from_str_name(&clct.distance)
-                        .ok_or(Error::InvalidDistanceName(clct.distance.clone()))?
-                        .into(),
-                    ..Default::default()
-                })),
-            }),
-            ..Default::default()
-        })
-        .await
-        .map_err(|e| Error::QdrantCreateError(format!("Failed to create collection: {}", e)))?;
         Ok(())
     }
 
-    pub async fn list_collections(&self) -> Result<Vec<String>> {
-        let qc = self.qc.lock().await;
-        let names = qc
+    /// Schema of the collection behind the alias if `name` is one.
+    async fn collection_schema(&self, name: &str) -> Result<Option<CollectionSchema>> {
+        let name = self
+            .alias_target(name)
+            .await?
+            .unwrap_or_else(|| name.to_string());
+        if !self.list_collections().await?.contains(&name) {
+            return Ok(None);
+        }
+        let info = self
+            .qc
+            .collection_info(&name)
+            .await
+            .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
+        let config = info
+            .result
+            .and_then(|info| info.config)
+            .and_then(|config| config.params)
+            .and_then(|params| params.vectors_config)
+            .and_then(|vectors| vectors.config);
+        to_collection_schema(&name, config).map(Some)
+    }
+
+    /// NOTE: A plain collection `clct.name` (created before the aliases) is kept as is,
+    ///       `reindex` refuses it.
+    async fn create_aliased_collection(&self, clct: &QdrantCollection) -> Result<()> {
+        if self.collection_schema(&clct.name).await?.is_some() {
+            return self.check_collection(clct).await;
+        }
+        let first = QdrantCollection {
+            name: format!("{}_v1", clct.name),
+            ..clct.clone()
+        };
+        self.create_collection(&first).await?;
+        self.swap_alias(&clct.name, &first.name).await
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let names = self
+            .qc
             .list_collections()
             .await
             .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
@@ -123,120 +221,405 @@ impl VecStore {
             .collect::<Vec<_>>())
     }
 
-    pub async fn delete_collection(&self, name: &str) -> Result<()> {
-        let qc = self.qc.lock().await;
-        qc.delete_collection(name).await.map_err(|_| {
+    async fn delete_collection(&self, name: &str) -> Result<()> {
+        self.qc.delete_collection(name).await.map_err(|_| {
             Error::QdrantDeleteError(format!("Failed to delete collection: {name}"))
         })?;
         Ok(())
     }
 
-    pub async fn reset_collection(&self, clct: &QdrantCollection) -> Result<()> {
-        self.delete_collection(&clct.name).await?;
-        self.create_collection(clct).await?;
+    async fn alias_target(&self, name: &str) -> Result<Option<String>> {
+        let aliases = self
+            .qc
+            .list_aliases()
+            .await
+            .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
+        Ok(aliases
+            .aliases
+            .into_iter()
+            .find(|a| a.alias_name == name)
+            .map(|a| a.collection_name))
+    }
+
+    /// One `update_aliases` call, the searches through `name` never fail.
+    ///
+    /// NOTE: A plain collection `name` is never replaced, `CollectionNotAliased`.
+    async fn swap_alias(&self, name: &str, collection: &str) -> Result<()> {
+        let mut actions = Vec::new();
+        if self.alias_target(name).await?.is_some() {
+            actions.push(AliasOperations {
+                action: Some(AliasAction::DeleteAlias(DeleteAlias {
+                    alias_name: name.to_string(),
+                })),
+            });
+        } else if self.list_collections().await?.iter().any(|c| c == name) {
+            return Err(Error::CollectionNotAliased(name.to_string()));
+        }
+        actions.push(AliasOperations {
+            action: Some(AliasAction::CreateAlias(CreateAlias {
+                collection_name: collection.to_string(),
+                alias_name: name.to_string(),
+            })),
+        });
+        self.qc
+            .update_aliases(ChangeAliases {
+                actions,
+                timeout: None,
+            })
+            .await
+            .map_err(|e| Error::QdrantUpdateError(format!("Failed to swap alias {name}: {e}")))?;
         Ok(())
     }
 
-    pub async fn update_points(
-        &self,
-        name: &str,
-        id_and_embs: Vec<(i64, Embedding)>,
-    ) -> Result<()> {
-        let qc = self.qc.lock().await;
-        let points = id_and_embs
+    async fn update_points(&self, name: &str, points: Vec<NewPoint>) -> Result<()> {
+        let points = points
             .into_iter()
             .map(|p| PointStruct {
-                id: Some((p.0 as u64).into()),
-                payload: HashMap::default(),
-                vectors: Some(p.1.into()),
+                id: Some(p.id.into()),
+                payload: to_qdrant_payload(&p.payload),
+                vectors: Some(p.embedding.into()),
             })
             .collect();
 
-        qc.upsert_points_blocking(name, points, None)
+        self.qc
+            .upsert_points_blocking(name, points, None)
             .await
             .map_err(|e| Error::QdrantUpdateError(e.to_string()))?;
         Ok(())
     }
 
-    pub async fn get_point_embeddings(&self, name: &str, ids: Vec<u64>) -> Result<Vec<Embedding>> {
-        let qc = self.qc.lock().await;
+    async fn get_point_embeddings(
+        &self,
+        name: &str,
+        ids: Vec<u64>,
+    ) -> Result<Vec<Point<NoScore, WithEmbedding>>> {
         let ids: Vec<PointId> = ids.into_iter().map(|i| i.into()).collect();
-        let point: GetResponse = qc
-            .get_points(name, &ids, Some(true), Some(false), None)
+        let point: GetResponse = self
+            .qc
+            .get_points(name, &ids, Some(true), Some(true), None)
             .await
             .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
-        Ok(point
+        point
             .result
             .into_iter()
-            .map(|p| {
-                let x = p.vectors.unwrap();
-                if let Vectors {
-                    vectors_options: Some(VectorsOptions::Vector(vec)),
-                } = x
-                {
-                    vec.data
-                } else {
-                    panic!("Wrong vector type")
-                }
+            .filter_map(|p| {
+                num_id(p.id).map(|id| {
+                    let embedding = WithEmbedding::from_vector(vector_data(p.vectors))
+                        .ok_or(Error::PointVectorMissing(id))?;
+                    Ok(Point {
+                        id,
+                        score: NoScore,
+                        embedding,
+                        payload: Some(from_qdrant_payload(&p.payload)?),
+                    })
+                })
             })
-            .collect())
+            .collect()
     }
 
-    pub async fn seach_points(
+    async fn seach_points<E: EmbeddingState>(
         &self,
         name: &str,
+        owner_id: i64,
         embedding: Vec<f32>,
-        limit: u64,
-    ) -> Result<Vec<(i64, f32)>> {
-        let qc = self.qc.lock().await;
-        let search_result = qc
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> Result<Vec<Point<WithScore, E>>> {
+        filter.validate()?;
+        let mut filter = to_qdrant_filter(filter);
+        filter
+            .must
+            .push(Condition::matches(OwnerId.key(), owner_id));
+        let search_result = self
+            .qc
             .search_points(&SearchPoints {
                 collection_name: name.to_string(),
                 vector: embedding,
-                limit,
-                with_payload: None,
+                filter: Some(filter),
+                limit: opts.limit,
+                offset: Some(opts.offset),
+                score_threshold: opts.score_threshold,
+                params: Some(SearchParams {
+                    hnsw_ef: opts.hnsw_ef,
+                    exact: Some(opts.exact),
+                    ..Default::default()
+                }),
+                with_payload: Some(opts.with_payload.into()),
+                with_vectors: Some(E::WITH_VECTORS.into()),
                 ..Default::default()
             })
             .await
             .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
-        Ok(search_result
+        search_result
             .result
             .into_iter()
             .filter_map(|p| {
-                if let PointId {
-                    point_id_options: Some(PointIdOptions::Num(id)),
-                } = p.id.unwrap()
-                {
-                    Some((id as i64, p.score))
-                } else {
-                    None
-                }
+                num_id(p.id).map(|id| {
+                    let payload = match opts.with_payload {
+                        true => Some(from_qdrant_payload(&p.payload)?),
+                        false => None,
+                    };
+                    Point::hit(id, p.score, vector_data(p.vectors), payload)
+                })
             })
-            .collect())
+            .collect()
+    }
+
+    async fn scroll_points(&self, name: &str) -> Result<Vec<(u64, PointPayload)>> {
+        let mut points = Vec::new();
+        let mut offset: Option<PointId> = None;
+        loop {
+            let page = self
+                .qc
+                .scroll(&ScrollPoints {
+                    collection_name: name.to_string(),
+                    offset,
+                    limit: Some(SCROLL_PAGE_SIZE),
+                    with_payload: Some(true.into()),
+                    with_vectors: Some(false.into()),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| Error::QdrantFetchError(e.to_string()))?;
+            for p in page.result {
+                if let Some(id) = num_id(p.id) {
+                    points.push((id, from_qdrant_payload(&p.payload)?));
+                }
+            }
+            match page.next_page_offset {
+                Some(next) => offset = Some(next),
+                None => return Ok(points),
+            }
+        }
     }
 
-    pub async fn delete_points(&self, name: &str, ids: Vec<u64>) -> Result<()> {
-        let qc = self.qc.lock().await;
+    async fn delete_points(&self, name: &str, ids: Vec<u64>) -> Result<()> {
         let ids: Vec<PointId> = ids.into_iter().map(|i| i.into()).collect();
-        qc.delete_points(name, &ids.into(), None)
+        self.qc
+            .delete_points(name, &ids.into(), None)
             .await
             .map_err(|e| Error::QdrantDeleteError(format!("Failed to delete points: {}", e)))?;
         Ok(())
     }
+
+    async fn delete_task_points(&self, name: &str, task_ids: Vec<i64>) -> Result<()> {
+        let filter = Filter::must([Condition::matches(TaskId.key(), task_ids)]);
+        self.qc
+            .delete_points(name, &filter.into(), None)
+            .await
+            .map_err(|e| Error::QdrantDeleteError(format!("Failed to delete points: {}", e)))?;
+        Ok(())
+    }
+
+    async fn delete_task_points_except(
+        &self,
+        name: &str,
+        task_id: i64,
+        keep: Vec<u64>,
+    ) -> Result<()> {
+        let filter = Filter {
+            must: vec![Condition::matches(TaskId.key(), task_id)],
+            must_not: vec![Condition::has_id(keep)],
+            ..Default::default()
+        };
+        self.qc
+            .delete_points(name, &filter.into(), None)
+            .await
+            .map_err(|e| Error::QdrantDeleteError(format!("Failed to delete points: {}", e)))?;
+        Ok(())
+    }
+}
+
+fn to_collection_schema(name: &str, config: Option<Config>) -> Result<CollectionSchema> {
+    let invalid = |msg: &str| Error::QdrantInvalidCollection(format!("{name}: {msg}"));
+    let (params, shape) = match config {
+        Some(Config::Params(params)) => (params, VectorsShape::Single),
+        Some(Config::ParamsMap(params_map)) => {
+            let mut named: Vec<(String, VectorParams)> = params_map.map.into_iter().collect();
+            named.sort_by(|a, b| a.0.cmp(&b.0));
+            let names = named.iter().map(|(name, _)| name.clone()).collect();
+            let (_, params) = named
+                .into_iter()
+                .next()
+                .ok_or_else(|| invalid("no named vector"))?;
+            (params, VectorsShape::Named(names))
+        }
+        None => return Err(invalid("no vectors config")),
+    };
+    let distance =
+        Distance::from_i32(params.distance).ok_or_else(|| invalid("unknown distance"))?;
+    Ok(CollectionSchema {
+        dim: params.size,
+        distance: VecDistance::from_name(distance.as_str_name())?,
+        shape,
+    })
+}
+
+fn to_qdrant_payload(payload: &PointPayload) -> HashMap<String, Value> {
+    let mut qd_payload = HashMap::from([
+        (OwnerId.key().to_string(), Value::from(payload.owner_id)),
+        (TaskId.key().to_string(), Value::from(payload.task_id)),
+        (
+            ChunkOffset.key().to_string(),
+            Value::from(payload.chunk_offset),
+        ),
+        (ChunkLen.key().to_string(), Value::from(payload.chunk_len)),
+    ]);
+    let tags = payload
+        .tags
+        .iter()
+        .map(|tag| Value::from(tag.clone()))
+        .collect();
+    qd_payload.insert(
+        Tags.key().to_string(),
+        Value {
+            kind: Some(Kind::ListValue(ListValue { values: tags })),
+        },
+    );
+    if let Some(status) = &payload.status {
+        qd_payload.insert(Status.key().to_string(), Value::from(status.clone()));
+    }
+    if let Some(created_at) = payload.created_at {
+        qd_payload.insert(CreatedAt.key().to_string(), Value::from(created_at));
+    }
+    qd_payload
+}
+
+fn from_qdrant_payload(payload: &HashMap<String, Value>) -> Result<PointPayload> {
+    let kind = |field: PayloadField| payload.get(field.key()).and_then(|v| v.kind.as_ref());
+    let invalid = |field: PayloadField| Error::QdrantInvalidPayload(field.key().to_string());
+    let int = |field: PayloadField| match kind(field) {
+        Some(Kind::IntegerValue(v)) => Ok(*v),
+        _ => Err(invalid(field)),
+    };
+    let tags = match kind(Tags) {
+        Some(Kind::ListValue(list)) => list
+            .values
+            .iter()
+            .map(|v| match &v.kind {
+                Some(Kind::StringValue(tag)) => Ok(tag.clone()),
+                _ => Err(invalid(Tags)),
+            })
+            .collect::<Result<_>>()?,
+        None => Vec::new(),
+        _ => return Err(invalid(Tags)),
+    };
+    let status = match kind(Status) {
+        Some(Kind::StringValue(status)) => Some(status.clone()),
+        None => None,
+        _ => return Err(invalid(Status)),
+    };
+    let created_at = match kind(CreatedAt) {
+        Some(_) => Some(int(CreatedAt)?),
+        None => None,
+    };
+    Ok(PointPayload {
+        owner_id: int(OwnerId)?,
+        task_id: int(TaskId)?,
+        chunk_offset: int(ChunkOffset)?,
+        chunk_len: int(ChunkLen)?,
+        tags,
+        status,
+        created_at,
+    })
+}
+
+/// Expects a validated filter, see `PointFilter::validate`.
+fn to_qdrant_filter(filter: &PointFilter) -> Filter {
+    let conditions = |conds: &[FieldCondition]| -> Vec<Condition> {
+        conds.iter().map(to_qdrant_condition).collect()
+    };
+    let mut qd_filter = Filter::must(conditions(&filter.must));
+    qd_filter.should = conditions(&filter.should);
+    qd_filter.must_not = conditions(&filter.must_not);
+    qd_filter
+}
+
+fn to_qdrant_condition(cond: &FieldCondition) -> Condition {
+    let key = cond.field().key();
+    match cond {
+        FieldCondition::Match(_, FieldValue::Integer(v)) => Condition::matches(key, *v),
+        FieldCondition::Match(_, FieldValue::Keyword(v)) => Condition::matches(key, v.clone()),
+        FieldCondition::MatchAny(field, values) if field.is_integer() => {
+            let ints: Vec<i64> = values
+                .iter()
+                .filter_map(|v| match v {
+                    FieldValue::Integer(v) => Some(*v),
+                    FieldValue::Keyword(_) => None,
+                })
+                .collect();
+            Condition::matches(key, ints)
+        }
+        FieldCondition::MatchAny(_, values) => {
+            let keywords: Vec<String> = values
+                .iter()
+                .filter_map(|v| match v {
+                    FieldValue::Keyword(v) => Some(v.clone()),
+                    FieldValue::Integer(_) => None,
+                })
+                .collect();
+            Condition::matches(key, keywords)
+        }
+        FieldCondition::Range(_, range) => Condition::range(
+            key,
+            QdrantRange {
+                gt: range.gt,
+                gte: range.gte,
+                lt: range.lt,
+                lte: range.lte,
+            },
+        ),
+    }
+}
+
+fn vector_data(vectors: Option<Vectors>) -> Option<Embedding> {
+    match vectors {
+        Some(Vectors {
+            vectors_options: Some(VectorsOptions::Vector(vec)),
+        }) => Some(vec.data),
+        _ => None,
+    }
+}
+
+fn num_id(id: Option<PointId>) -> Option<u64> {
+    if let Some(PointId {
+        point_id_options: Some(PointIdOptions::Num(id)),
+    }) = id
+    {
+        Some(id)
+    } else {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::time::Duration;
+    use std::time::Instant;
 
     use crate::config;
+    use crate::model::store::Range;
+    use qdrant_client::qdrant::VectorParamsMap;
 
     #[allow(unused)]
     use super::*;
     use anyhow::Result;
     use serial_test::serial;
-    use tokio::time::sleep;
+
+    fn points(owner_id: i64, id_and_embs: Vec<(u64, Embedding)>) -> Vec<NewPoint> {
+        id_and_embs
+            .into_iter()
+            .map(|(id, embedding)| NewPoint {
+                id,
+                embedding,
+                payload: PointPayload {
+                    owner_id,
+                    task_id: id as i64,
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
 
     #[serial]
     #[tokio::test]
@@ -249,7 +632,7 @@ mod tests {
         // };
 
         let clct = config().qdrant.collections.first().unwrap();
-        vs.create_collection(&clct).await?;
+        vs.create_collection(clct).await?;
         let names = vs.list_collections().await?;
         assert!(names.contains(&clct.name));
         vs.delete_collection(&clct.name).await?;
@@ -266,29 +649,139 @@ mod tests {
         //     distance: "Cosine".to_string(),
         // };
         let clct = config().qdrant.collections.first().unwrap();
-        vs.create_collection(&clct).await?;
+        vs.create_collection(clct).await?;
         vs.delete_collection(&clct.name).await?;
         let names = vs.list_collections().await?;
         assert!(!names.contains(&clct.name));
         Ok(())
     }
 
+    #[test]
+    fn test_to_collection_schema() -> Result<()> {
+        let params = |size, distance: Distance| VectorParams {
+            size,
+            distance: distance.into(),
+            ..Default::default()
+        };
+        let schema = to_collection_schema("foo", Some(Config::Params(params(4, Distance::Dot))))?;
+        assert_eq!(
+            schema,
+            CollectionSchema {
+                dim: 4,
+                distance: VecDistance::Dot,
+                shape: VectorsShape::Single,
+            }
+        );
+
+        let named = VectorParamsMap {
+            map: HashMap::from([
+                ("text".to_string(), params(8, Distance::Cosine)),
+                ("image".to_string(), params(2, Distance::Euclid)),
+            ]),
+        };
+        let schema = to_collection_schema("foo", Some(Config::ParamsMap(named)))?;
+        assert_eq!(schema.dim, 2);
+        assert_eq!(
+            schema.shape,
+            VectorsShape::Named(vec!["image".to_string(), "text".to_string()])
+        );
+        assert!(matches!(
+            to_collection_schema("foo", None),
+            Err(Error::QdrantInvalidCollection(_))
+        ));
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_create_collection_err_schema_mismatch() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let changed = QdrantCollection {
+            dim: clct.dim + 1,
+            distance: "Dot".to_string(),
+            ..clct.clone()
+        };
+        let res = vs.create_collection(&changed).await;
+        let Err(Error::CollectionSchemaMismatch {
+            expected, actual, ..
+        }) = res
+        else {
+            panic!("Expected CollectionSchemaMismatch, got {:?}", res);
+        };
+        assert_eq!(expected.dim, clct.dim + 1);
+        assert_eq!(actual.dim, clct.dim);
+        assert_eq!(actual.shape, VectorsShape::Single);
+        // -- The collection is kept.
+        assert!(vs.list_collections().await?.contains(&clct.name));
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_create_aliased_collection_ok() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = QdrantCollection {
+            name: "test_aliased".to_string(),
+            ..config().qdrant.collections.first().unwrap().clone()
+        };
+        for name in ["test_aliased_v1", "test_aliased_v2"] {
+            vs.delete_collection(name).await?;
+        }
+
+        // -- Twice, the second call only checks it.
+        for _ in 0..2 {
+            vs.create_aliased_collection(&clct).await?;
+            let target = vs.alias_target(&clct.name).await?;
+            assert_eq!(target.as_deref(), Some("test_aliased_v1"));
+        }
+
+        // -- Swapped, the previous collection is kept for the caller to drop.
+        let v2 = QdrantCollection {
+            name: "test_aliased_v2".to_string(),
+            ..clct.clone()
+        };
+        vs.create_collection(&v2).await?;
+        vs.swap_alias(&clct.name, &v2.name).await?;
+        let target = vs.alias_target(&clct.name).await?;
+        assert_eq!(target.as_deref(), Some("test_aliased_v2"));
+        assert!(vs
+            .list_collections()
+            .await?
+            .contains(&"test_aliased_v1".to_string()));
+
+        // -- A plain collection is never replaced.
+        let res = vs.swap_alias("test_aliased_v1", &v2.name).await;
+        assert!(
+            matches!(res, Err(Error::CollectionNotAliased(_))),
+            "{res:?}"
+        );
+
+        vs.delete_collection("test_aliased_v1").await?;
+        vs.delete_collection("test_aliased_v2").await?;
+        Ok(())
+    }
+
     #[serial]
     #[tokio::test]
     async fn test_save_points_ok() -> Result<()> {
         // let mut vs0 = VecStore::from_config().await?;
         // let x = vs0.list_collections().await?;
         // println!("{:?}", x);
-        let mut vs = VecStore::from_config().await?;
+        let vs = VecStore::from_config().await?;
         let clct = config().qdrant.collections.first().unwrap();
         vs.create_collection(clct).await?;
         let id_and_embs = vec![
             (1, vec![1.0; clct.dim as usize]),
             (2, vec![2.0; clct.dim as usize]),
         ];
-        vs.update_points(&clct.name, id_and_embs).await?;
-        let embs = vs.get_point_embeddings(&clct.name, vec![1, 2]).await?;
-        assert_eq!(embs.len(), 2);
+        vs.update_points(&clct.name, points(0, id_and_embs)).await?;
+        let points = vs.get_point_embeddings(&clct.name, vec![1, 2]).await?;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].embedding().len(), clct.dim as usize);
+        assert_eq!(points[0].payload.as_ref().map(|p| p.task_id), Some(1));
         vs.delete_collection(&clct.name).await?;
         Ok(())
     }
@@ -296,14 +789,14 @@ mod tests {
     #[serial]
     #[tokio::test]
     async fn test_save_points_err_dim_mismatch() -> Result<()> {
-        let mut vs = VecStore::from_config().await?;
+        let vs = VecStore::from_config().await?;
         let clct = config().qdrant.collections.first().unwrap();
         vs.create_collection(clct).await?;
         let id_and_embs = vec![
             (1, vec![1.0; clct.dim as usize - 1]),
             (2, vec![2.0; clct.dim as usize - 1]),
         ];
-        let res = vs.update_points(&clct.name, id_and_embs).await;
+        let res = vs.update_points(&clct.name, points(0, id_and_embs)).await;
         assert!(
             matches!(res, Err(Error::QdrantUpdateError(_))),
             "Expected QdrantUpdateError, got {:?}",
@@ -316,24 +809,273 @@ mod tests {
     #[serial]
     #[tokio::test]
     async fn test_search_points_ok() -> Result<()> {
-        let mut vs = VecStore::from_config().await?;
+        let vs = VecStore::from_config().await?;
         let clct = config().qdrant.collections.first().unwrap();
         vs.create_collection(clct).await?;
         let id_and_embs = vec![
             (1, vec![1.0; clct.dim as usize]),
             (2, vec![2.0; clct.dim as usize]),
         ];
-        vs.update_points(&clct.name, id_and_embs).await?;
+        vs.update_points(&clct.name, points(0, id_and_embs)).await?;
         let search_result = vs
-            .seach_points(&clct.name, vec![1.0; clct.dim as usize], 2)
+            .seach_points::<NoEmbedding>(
+                &clct.name,
+                0,
+                vec![1.0; clct.dim as usize],
+                &PointFilter::default(),
+                &SearchOptions::new(2),
+            )
             .await?;
         assert_eq!(search_result.len(), 2);
-        for (id, score) in &search_result {
-            let diff = (score - 1.0).abs();
+        for hit in &search_result {
+            let diff = (hit.score() - 1.0).abs();
             assert!(diff < 0.0001);
         }
         vs.delete_collection(&clct.name).await?;
         Ok(())
     }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_options_ok() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let dim = clct.dim as usize;
+        let mut orthogonal = vec![0.0; dim];
+        orthogonal[0] = 1.0;
+        let id_and_embs = vec![(1, vec![1.0; dim]), (2, vec![1.0; dim]), (3, orthogonal)];
+        vs.update_points(&clct.name, points(0, id_and_embs)).await?;
+        let all = PointFilter::default();
+
+        // -- Exact search above a threshold, paged by one.
+        let mut ids = Vec::new();
+        for offset in 0..3 {
+            let opts = SearchOptions {
+                offset,
+                score_threshold: Some(0.5),
+                exact: true,
+                ..SearchOptions::new(1)
+            };
+            let hits = vs
+                .seach_points::<NoEmbedding>(&clct.name, 0, vec![1.0; dim], &all, &opts)
+                .await?;
+            ids.extend(hits.iter().map(|h| h.id));
+        }
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+
+        // -- Vectors without payload.
+        let opts = SearchOptions {
+            hnsw_ef: Some(128),
+            with_payload: false,
+            ..SearchOptions::new(1)
+        };
+        let hits = vs
+            .seach_points::<WithEmbedding>(&clct.name, 0, vec![1.0; dim], &all, &opts)
+            .await?;
+        assert!(hits[0].payload.is_none());
+        assert_eq!(hits[0].embedding().len(), dim);
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_scroll_points_ok() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let id_and_embs = (1..=300)
+            .map(|id| (id, vec![1.0; clct.dim as usize]))
+            .collect();
+        vs.update_points(&clct.name, points(0, id_and_embs)).await?;
+        let mut ids: Vec<u64> = vs
+            .scroll_points(&clct.name)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (1..=300).collect::<Vec<u64>>());
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_other_owner_empty() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let id_and_embs = vec![(1, vec![1.0; clct.dim as usize])];
+        vs.update_points(&clct.name, points(1000, id_and_embs))
+            .await?;
+        let search_result = vs
+            .seach_points::<NoEmbedding>(
+                &clct.name,
+                1001,
+                vec![1.0; clct.dim as usize],
+                &PointFilter::default(),
+                &SearchOptions::new(2),
+            )
+            .await?;
+        assert!(search_result.is_empty());
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_delete_task_points_ok() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let id_and_embs = (1..=3)
+            .map(|id| (id, vec![1.0; clct.dim as usize]))
+            .collect();
+        vs.update_points(&clct.name, points(0, id_and_embs)).await?;
+        vs.delete_task_points(&clct.name, vec![1, 2]).await?;
+        let ids: Vec<u64> = vs
+            .scroll_points(&clct.name)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![3]);
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_points_filter_ok() -> Result<()> {
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let mut pts = points(
+            0,
+            (1..=3)
+                .map(|id| (id, vec![1.0; clct.dim as usize]))
+                .collect(),
+        );
+        pts[0].payload.tags = vec!["backend".to_string(), "api".to_string()];
+        pts[0].payload.status = Some("open".to_string());
+        pts[1].payload.tags = vec!["backend".to_string()];
+        pts[1].payload.status = Some("done".to_string());
+        pts[2].payload.created_at = Some(1_700_000_000);
+        vs.update_points(&clct.name, pts).await?;
+
+        let search = |filter: PointFilter| {
+            let vs = vs.clone();
+            async move {
+                let hits = vs
+                    .seach_points::<NoEmbedding>(
+                        &clct.name,
+                        0,
+                        vec![1.0; clct.dim as usize],
+                        &filter,
+                        &SearchOptions::new(10),
+                    )
+                    .await?;
+                let mut ids: Vec<u64> = hits.iter().map(|h| h.id).collect();
+                ids.sort();
+                Ok::<_, Error>(ids)
+            }
+        };
+        let open_backend = PointFilter::default()
+            .must(FieldCondition::Match(Status, "open".into()))
+            .must(FieldCondition::Match(Tags, "backend".into()));
+        assert_eq!(search(open_backend).await?, vec![1]);
+        let not_done =
+            PointFilter::default().must_not(FieldCondition::Match(Status, "done".into()));
+        assert_eq!(search(not_done).await?, vec![1, 3]);
+        let recent_or_api = PointFilter::default()
+            .should(FieldCondition::Range(
+                CreatedAt,
+                Range {
+                    gte: Some(1_600_000_000.),
+                    ..Default::default()
+                },
+            ))
+            .should(FieldCondition::MatchAny(
+                Tags,
+                vec!["api".into(), "ui".into()],
+            ));
+        assert_eq!(search(recent_or_api).await?, vec![1, 3]);
+
+        // -- The payload comes back typed.
+        let (_, payload) = vs
+            .scroll_points(&clct.name)
+            .await?
+            .into_iter()
+            .find(|(id, _)| *id == 1)
+            .unwrap();
+        assert_eq!(payload.tags, vec!["backend", "api"]);
+        assert_eq!(payload.status.as_deref(), Some("open"));
+        vs.delete_collection(&clct.name).await?;
+        Ok(())
+    }
+
+    /// Searches per second of 1, 4 and 16 callers sharing one store. Run with
+    /// `cargo test --release bench_search_throughput -- --ignored --nocapture`.
+    #[serial]
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bench_search_throughput() -> Result<()> {
+        const SEARCHES: usize = 512;
+        let vs = VecStore::from_config().await?;
+        let clct = config().qdrant.collections.first().unwrap();
+        vs.create_collection(clct).await?;
+        let dim = clct.dim as usize;
+        for batch in 0..4u64 {
+            let id_and_embs = (batch * 250 + 1..=(batch + 1) * 250)
+                .map(|id| {
+                    let emb = (0..dim)
+                        .map(|i| ((id as usize * 31 + i) % 97) as f32)
+                        .collect();
+                    (id, emb)
+                })
+                .collect();
+            vs.update_points(&clct.name, points(0, id_and_embs)).await?;
+        }
+
+        let mut rates = Vec::new();
+        for callers in [1, 4, 16] {
+            let start = Instant::now();
+            let tasks: Vec<_> = (0..callers)
+                .map(|caller| {
+                    let vs = vs.clone();
+                    let name = clct.name.clone();
+                    tokio::spawn(async move {
+                        for i in 0..SEARCHES / callers {
+                            let query = vec![(caller + i + 1) as f32; dim];
+                            vs.seach_points::<NoEmbedding>(
+                                &name,
+                                0,
+                                query,
+                                &PointFilter::default(),
+                                &SearchOptions::new(10),
+                            )
+                            .await?;
+                        }
+                        Ok::<_, Error>(())
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await??;
+            }
+            let rate = SEARCHES as f64 / start.elapsed().as_secs_f64();
+            println!("{callers:>3} callers: {rate:>8.0} searches/s");
+            rates.push(rate);
+        }
+        vs.delete_collection(&clct.name).await?;
+        assert!(
+            rates[2] > rates[0],
+            "No scaling with the callers: {rates:?}"
+        );
+        Ok(())
+    }
 }
 // endregion: --- Test
@@ -1,83 +1,139 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::{FromRow, PgConnection};
 
+use crate::config::config;
 use crate::ctx::Ctx;
+use crate::model::chunker::{chunk_text, Chunk};
 use crate::model::error::{Error, Result};
-use crate::model::ModelManager;
+use crate::model::outbox::{OutboxBmc, OutboxOp};
+use crate::model::store::{NewPoint, NoEmbedding, PointFilter, PointPayload, SearchOptions};
+use crate::model::{ModelManager, VectorBackend};
 
 use super::embedder::Embedder;
 
-///
-/// Create: given a long story, truncate it to a short snippets and save to db
-///     - need a db -> qdrant transform
-
 pub trait VsBmc {
     const COLLECTION_NAME: &'static str;
-    const DB_TABLE_NAME: &'static str;
 }
 
+/// Create: save the story to db, its points follow
+///     - a story is split in overlapping chunks (see `chunker`), one point per chunk
+///     - the point payload carries the task id, the chunk offset/len in the story
+///       and the status/tags/created_at of the task, for the search filters
+///     - a search collapses the chunk hits to their task, with the best chunk as snippet
+///
+/// All operations are scoped to `ctx.user_id`, a task of another user is `EntityNotFound`.
+///
+/// Writes only touch postgres, the vector store is updated by the outbox worker (see `outbox`).
+/// A transactional backend (pgvector) is written in the story transaction instead.
 pub struct TaskBmc;
 
+const SEARCH_OVERFETCH: u64 = 4;
+/// Chunks of one story, the chunk index is the low 16 bits of the point id (see `point_id`).
+const MAX_CHUNKS: usize = 1 << 16;
+
 impl VsBmc for TaskBmc {
     const COLLECTION_NAME: &'static str = "task";
-    const DB_TABLE_NAME: &'static str = "story";
 }
 
 #[derive(Debug, Clone, FromRow, Serialize)]
 pub struct Task {
     pub id: i64,
     pub story: String, // Keep simple first
+    pub status: String,
+    pub tags: Vec<String>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct TaskHit {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub task: Task,
+    pub score: f32,
+    /// The best matching chunk of the story.
+    pub snippet: String,
+}
+
+#[derive(Deserialize, Clone, Default)]
 pub struct TaskForCreate {
     pub story: String,
+    /// Defaults to `open`.
+    pub status: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Deserialize, Clone)]
+/// `status` and `tags` are kept if not given.
+#[derive(Deserialize, Clone, Default)]
 pub struct TaskForUpdate {
+    #[serde(default)] // the web layer takes the id from the path
+    pub id: i64,
+    pub story: String,
+    pub status: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// The story row and what goes in the payload of its points.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct TaskSource {
     pub id: i64,
+    pub owner_id: i64,
     pub story: String,
+    pub status: String,
+    pub tags: Vec<String>,
+    /// Unix timestamp (secs).
+    pub created_at: i64,
 }
 
 // endregion: --- Task Types
 
 impl TaskBmc {
     pub async fn create(
-        _ctx: Ctx,
-        mm: ModelManager<impl Embedder>,
+        ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
         task: TaskForCreate,
     ) -> Result<i64> {
+        Self::story_chunks(&task.story)?;
+        let mut tx = mm.db.begin().await?;
         let (id,): (i64,) = sqlx::query_as(
             "
-            INSERT INTO story (story) VALUES ($1) RETURNING id
+            INSERT INTO story (owner_id, story, status, tags)
+            VALUES ($1, $2, COALESCE($3, 'open'), $4) RETURNING id
             ",
         )
+        .bind(ctx.user_id)
         .bind(&task.story)
-        .fetch_one(&mm.db)
+        .bind(&task.status)
+        .bind(&task.tags)
+        .fetch_one(&mut *tx)
         .await?;
-
-        let emb = mm.embedder.embed(&task.story).await?;
-        mm.vs
-            .update_points(Self::COLLECTION_NAME, vec![(id, emb)])
-            .await?;
+        Self::sync_upsert(&mut tx, &mm, ctx.user_id, id).await?;
+        tx.commit().await?;
 
         Ok(id)
     }
 
     pub async fn update(
-        _ctx: Ctx,
-        mm: ModelManager<impl Embedder>,
+        ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
         task: TaskForUpdate,
     ) -> Result<()> {
+        Self::story_chunks(&task.story)?;
+        let mut tx = mm.db.begin().await?;
         let count = sqlx::query(
             "
-            UPDATE story SET story = $1 WHERE id = $2
+            UPDATE story
+            SET story = $1, status = COALESCE($4, status), tags = COALESCE($5, tags),
+                updated_at = now()
+            WHERE id = $2 AND owner_id = $3
             ",
         )
         .bind(&task.story)
         .bind(task.id)
-        .execute(&mm.db)
+        .bind(ctx.user_id)
+        .bind(&task.status)
+        .bind(&task.tags)
+        .execute(&mut *tx)
         .await?
         .rows_affected();
         if count == 0 {
@@ -86,34 +142,47 @@ impl TaskBmc {
                 id: task.id,
             });
         };
-        let emb = mm.embedder.embed(&task.story).await?;
-        mm.vs
-            .update_points(Self::COLLECTION_NAME, vec![(task.id, emb)])
-            .await?;
+        Self::sync_upsert(&mut tx, &mm, ctx.user_id, task.id).await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    pub async fn read(_ctx: Ctx, mm: ModelManager<impl Embedder>, id: i64) -> Result<Task> {
+    pub async fn read(
+        ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        id: i64,
+    ) -> Result<Task> {
         let task = sqlx::query_as(
             "
-            SELECT id, story FROM story WHERE id = $1
+            SELECT id, story, status, tags FROM story WHERE id = $1 AND owner_id = $2
             ",
         )
         .bind(id)
-        .fetch_one(&mm.db)
-        .await?;
+        .bind(ctx.user_id)
+        .fetch_optional(&mm.db)
+        .await?
+        .ok_or(Error::EntityNotFound {
+            entity: Self::COLLECTION_NAME,
+            id,
+        })?;
         Ok(task)
     }
 
-    pub async fn delete(_ctx: Ctx, mm: ModelManager<impl Embedder>, id: i64) -> Result<()> {
+    pub async fn delete(
+        ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        id: i64,
+    ) -> Result<()> {
+        let mut tx = mm.db.begin().await?;
         let count = sqlx::query(
             "
-            DELETE FROM story WHERE id = $1
+            DELETE FROM story WHERE id = $1 AND owner_id = $2
             ",
         )
         .bind(id)
-        .execute(&mm.db)
+        .bind(ctx.user_id)
+        .execute(&mut *tx)
         .await?
         .rows_affected();
         if count == 0 {
@@ -122,40 +191,286 @@ impl TaskBmc {
                 id,
             });
         };
-        mm.vs
-            .delete_points(Self::COLLECTION_NAME, vec![id as u64])
+        Self::sync_delete(&mut tx, &mm, ctx.user_id, id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Point id of the chunk, the chunk index in the low 16 bits.
+    ///
+    /// NOTE: `chunk_index` must be below `MAX_CHUNKS`, see `story_chunks`.
+    pub(crate) fn point_id(task_id: i64, chunk_index: usize) -> u64 {
+        ((task_id as u64) << 16) | chunk_index as u64
+    }
+
+    /// The chunks of the story, `TaskTooManyChunks` above `MAX_CHUNKS`
+    /// (their point ids would overlap the ones of the next tasks).
+    fn story_chunks(story: &str) -> Result<Vec<Chunk>> {
+        let conf = &config().chunking;
+        let chunks = chunk_text(story, conf.max_tokens, conf.overlap_tokens);
+        check_chunk_count(chunks.len())?;
+        Ok(chunks)
+    }
+
+    /// `None` if the story is gone. Reads through `conn`, so it sees the changes of its transaction.
+    pub(crate) async fn source(conn: &mut PgConnection, id: i64) -> Result<Option<TaskSource>> {
+        let src = sqlx::query_as(
+            "
+            SELECT id, owner_id, story, status, tags,
+                extract(epoch FROM created_at)::BIGINT AS created_at
+            FROM story WHERE id = $1
+            ",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+        Ok(src)
+    }
+
+    /// The stories with an id above `after_id`, by id, to page through the whole table.
+    /// With `changed_since`, only the ones created or updated since.
+    pub(crate) async fn sources_after(
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+        after_id: i64,
+        changed_since: Option<OffsetDateTime>,
+        limit: i64,
+    ) -> Result<Vec<TaskSource>> {
+        let srcs = sqlx::query_as(
+            "
+            SELECT id, owner_id, story, status, tags,
+                extract(epoch FROM created_at)::BIGINT AS created_at
+            FROM story WHERE id > $1 AND ($3::TIMESTAMPTZ IS NULL OR updated_at >= $3)
+            ORDER BY id
+            LIMIT $2
+            ",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .bind(changed_since)
+        .fetch_all(&mm.db)
+        .await?;
+        Ok(srcs)
+    }
+
+    /// Chunk the stories and embed all their chunks in one batch, one point per chunk.
+    pub(crate) async fn embed_points(
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+        srcs: &[TaskSource],
+    ) -> Result<Vec<NewPoint>> {
+        let mut chunks = Vec::new();
+        for src in srcs {
+            for (index, chunk) in Self::story_chunks(&src.story)?.into_iter().enumerate() {
+                chunks.push((src, index, chunk));
+            }
+        }
+        let embs = mm
+            .embedder
+            .embeds(
+                chunks
+                    .iter()
+                    .map(|(_, _, chunk)| chunk.text.as_str())
+                    .collect(),
+            )
             .await?;
+        let points = chunks
+            .into_iter()
+            .zip(embs)
+            .map(|((src, index, chunk), embedding)| NewPoint {
+                id: Self::point_id(src.id, index),
+                embedding,
+                payload: PointPayload {
+                    owner_id: src.owner_id,
+                    task_id: src.id,
+                    chunk_offset: chunk.offset as i64,
+                    chunk_len: chunk.len as i64,
+                    tags: src.tags.clone(),
+                    status: Some(src.status.clone()),
+                    created_at: Some(src.created_at),
+                },
+            })
+            .collect();
+        Ok(points)
+    }
+
+    /// Write the points of the story in `conn` if the backend is transactional, else enqueue it.
+    ///
+    /// NOTE: The previous points are deleted first, the new story may have fewer chunks.
+    async fn sync_upsert(
+        conn: &mut PgConnection,
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+        owner_id: i64,
+        id: i64,
+    ) -> Result<()> {
+        if mm.vs.is_transactional() {
+            let src = Self::source(conn, id).await?.ok_or(Error::EntityNotFound {
+                entity: Self::COLLECTION_NAME,
+                id,
+            })?;
+            let points = Self::embed_points(mm, &[src]).await?;
+            mm.vs
+                .delete_task_points_tx(conn, Self::COLLECTION_NAME, vec![id])
+                .await?;
+            mm.vs
+                .update_points_tx(conn, Self::COLLECTION_NAME, points)
+                .await?;
+        } else {
+            OutboxBmc::enqueue(conn, id, owner_id, OutboxOp::Upsert).await?;
+        }
+        Ok(())
+    }
+
+    async fn sync_delete(
+        conn: &mut PgConnection,
+        mm: &ModelManager<impl Embedder, impl VectorBackend>,
+        owner_id: i64,
+        id: i64,
+    ) -> Result<()> {
+        if mm.vs.is_transactional() {
+            mm.vs
+                .delete_task_points_tx(conn, Self::COLLECTION_NAME, vec![id])
+                .await?;
+        } else {
+            OutboxBmc::enqueue(conn, id, owner_id, OutboxOp::Delete).await?;
+        }
         Ok(())
     }
+
+    /// Embed the `query` and return the closest tasks matching the `filter`, best match first.
+    /// `opts.limit` and `opts.offset` count tasks, not chunks.
+    ///
+    /// NOTE: `SEARCH_OVERFETCH` times `offset + limit` chunks are fetched, so that enough tasks
+    ///       are usually left once the chunks of the same task are collapsed.
+    pub async fn search(
+        ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        query: &str,
+        filter: &PointFilter,
+        opts: &SearchOptions,
+    ) -> Result<Vec<TaskHit>> {
+        let emb = mm.embedder.embed(query).await?;
+        let chunk_opts = SearchOptions {
+            limit: opts
+                .offset
+                .saturating_add(opts.limit)
+                .saturating_mul(SEARCH_OVERFETCH),
+            offset: 0,
+            with_payload: true,
+            ..opts.clone()
+        };
+        let hits = mm
+            .vs
+            .seach_points::<NoEmbedding>(
+                Self::COLLECTION_NAME,
+                ctx.user_id,
+                emb,
+                filter,
+                &chunk_opts,
+            )
+            .await?;
+
+        // -- Keep the best chunk of each task, the hits are best first.
+        let mut seen: Vec<i64> = Vec::new();
+        let mut ids: Vec<i64> = Vec::new();
+        let mut scores: Vec<f32> = Vec::new();
+        let mut offsets: Vec<i64> = Vec::new();
+        let mut lens: Vec<i64> = Vec::new();
+        for (score, payload) in hits
+            .into_iter()
+            .filter_map(|h| Some((h.score(), h.payload?)))
+        {
+            if ids.len() as u64 == opts.limit {
+                break;
+            }
+            if seen.contains(&payload.task_id) {
+                continue;
+            }
+            seen.push(payload.task_id);
+            if seen.len() as u64 <= opts.offset {
+                continue;
+            }
+            ids.push(payload.task_id);
+            scores.push(score);
+            offsets.push(payload.chunk_offset);
+            lens.push(payload.chunk_len);
+        }
+
+        // Join the hits back to the rows, keeping the vector store ranking.
+        let tasks = sqlx::query_as(
+            "
+            SELECT s.id, s.story, s.status, s.tags, hit.score,
+                substr(s.story, (hit.chunk_offset + 1)::INT, hit.chunk_len::INT) AS snippet
+            FROM unnest($1::BIGINT[], $2::REAL[], $3::BIGINT[], $4::BIGINT[])
+                WITH ORDINALITY AS hit(id, score, chunk_offset, chunk_len, rank)
+            JOIN story s ON s.id = hit.id AND s.owner_id = $5
+            ORDER BY hit.rank
+            ",
+        )
+        .bind(&ids)
+        .bind(&scores)
+        .bind(&offsets)
+        .bind(&lens)
+        .bind(ctx.user_id)
+        .fetch_all(&mm.db)
+        .await?;
+        Ok(tasks)
+    }
+}
+
+fn check_chunk_count(chunks: usize) -> Result<()> {
+    if chunks > MAX_CHUNKS {
+        return Err(Error::TaskTooManyChunks {
+            chunks,
+            max_chunks: MAX_CHUNKS,
+        });
+    }
+    Ok(())
 }
 
 // TODO testing
 // region:   --- Test
 #[cfg(test)]
 mod tests {
-    use crate::{_dev_utils, model::embedder::OpenAIEmbedder};
+    use crate::model::store::{FieldCondition, PayloadField};
+    use crate::model::MemVecStore;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder, model::user::UserBmc};
 
     #[allow(unused)]
     use super::*;
     use anyhow::Result;
     use serial_test::serial;
 
+    #[test]
+    fn test_check_chunk_count_boundary() {
+        assert!(check_chunk_count(MAX_CHUNKS).is_ok());
+        assert!(matches!(
+            check_chunk_count(MAX_CHUNKS + 1),
+            Err(Error::TaskTooManyChunks { chunks, .. }) if chunks == MAX_CHUNKS + 1
+        ));
+        // -- The last chunk of a task stays below the first one of the next task.
+        assert!(TaskBmc::point_id(1, MAX_CHUNKS - 1) < TaskBmc::point_id(2, 0));
+    }
+
     #[serial]
     #[tokio::test]
     async fn test_create_ok() -> Result<()> {
         _dev_utils::init_dev().await;
         let ctx = Ctx::root_ctx();
-        let mm = ModelManager::<OpenAIEmbedder>::from_config().await?;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
         let task = TaskForCreate {
             story: "This is a story".to_string(),
+            ..Default::default()
         };
         let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
-        let embs = mm
+        OutboxBmc::process_all(mm.clone()).await?;
+        let points = mm
             .vs
-            .get_point_embeddings(TaskBmc::COLLECTION_NAME, vec![id as u64])
+            .get_point_embeddings(TaskBmc::COLLECTION_NAME, vec![TaskBmc::point_id(id, 0)])
             .await?;
-        assert_eq!(embs.len(), 1);
-        TaskBmc::delete(ctx, mm, id).await?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].payload.as_ref().map(|p| p.task_id), Some(id));
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
         Ok(())
     }
 
@@ -164,15 +479,17 @@ mod tests {
     async fn test_read_ok() -> Result<()> {
         _dev_utils::init_dev().await;
         let ctx = Ctx::root_ctx();
-        let mm = ModelManager::<OpenAIEmbedder>::from_config().await?;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
         let task = TaskForCreate {
             story: "This is a story".to_string(),
+            ..Default::default()
         };
         let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
         let task = TaskBmc::read(ctx.clone(), mm.clone(), id).await?;
         assert_eq!(task.story, "This is a story");
 
-        TaskBmc::delete(ctx, mm, id).await?;
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
         Ok(())
     }
 
@@ -181,19 +498,22 @@ mod tests {
     async fn test_update_ok() -> Result<()> {
         _dev_utils::init_dev().await;
         let ctx = Ctx::root_ctx();
-        let mm = ModelManager::<OpenAIEmbedder>::from_config().await?;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
         let task_for_create = TaskForCreate {
             story: "This is a story".to_string(),
+            ..Default::default()
         };
         let id = TaskBmc::create(ctx.clone(), mm.clone(), task_for_create.clone()).await?;
         let task_for_update = TaskForUpdate {
             id,
             story: "This is a new story".to_string(),
+            ..Default::default()
         };
         TaskBmc::update(ctx.clone(), mm.clone(), task_for_update).await?;
         let task = TaskBmc::read(ctx.clone(), mm.clone(), id).await?;
         assert_eq!(task.story, "This is a new story");
-        TaskBmc::delete(ctx, mm, id).await?;
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
         Ok(())
     }
 
@@ -202,14 +522,262 @@ mod tests {
     async fn test_delete_ok() -> Result<()> {
         _dev_utils::init_dev().await;
         let ctx = Ctx::root_ctx();
-        let mm = ModelManager::<OpenAIEmbedder>::from_config().await?;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
         let task = TaskForCreate {
             story: "This is a story".to_string(),
+            ..Default::default()
         };
         let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
         TaskBmc::delete(ctx.clone(), mm.clone(), id).await?;
         let task = TaskBmc::read(ctx.clone(), mm.clone(), id).await;
         assert!(task.is_err());
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let mut ids = Vec::new();
+        for story in ["Fix the login page", "Bake a chocolate cake"] {
+            let task = TaskForCreate {
+                story: story.to_string(),
+                ..Default::default()
+            };
+            ids.push(TaskBmc::create(ctx.clone(), mm.clone(), task).await?);
+        }
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        let hits = TaskBmc::search(
+            ctx.clone(),
+            mm.clone(),
+            "chocolate cake recipe",
+            &PointFilter::default(),
+            &SearchOptions::new(2),
+        )
+        .await?;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].task.story, "Bake a chocolate cake");
+        assert_eq!(hits[0].snippet, "Bake a chocolate cake");
+        assert!(hits[0].score >= hits[1].score);
+
+        for id in ids {
+            TaskBmc::delete(ctx.clone(), mm.clone(), id).await?;
+        }
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_options_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let mut ids = Vec::new();
+        for story in [
+            "Bake a chocolate cake",
+            "Bake a chocolate cake for the team",
+            "Renew the office lease",
+        ] {
+            let task = TaskForCreate {
+                story: story.to_string(),
+                ..Default::default()
+            };
+            ids.push(TaskBmc::create(ctx.clone(), mm.clone(), task).await?);
+        }
+        OutboxBmc::process_all(mm.clone()).await?;
+        let all = PointFilter::default();
+
+        // -- Pages of one task.
+        let mut paged = Vec::new();
+        for offset in 0..2 {
+            let opts = SearchOptions {
+                offset,
+                ..SearchOptions::new(1)
+            };
+            let hits =
+                TaskBmc::search(ctx.clone(), mm.clone(), "chocolate cake", &all, &opts).await?;
+            assert_eq!(hits.len(), 1);
+            paged.push(hits[0].task.id);
+        }
+        paged.sort();
+        assert_eq!(paged, ids[..2]);
+
+        // -- The lease does not make the threshold.
+        let opts = SearchOptions {
+            score_threshold: Some(0.3),
+            ..SearchOptions::new(10)
+        };
+        let hits = TaskBmc::search(ctx.clone(), mm.clone(), "chocolate cake", &all, &opts).await?;
+        assert!(hits.iter().all(|hit| hit.task.id != ids[2]));
+        assert!(hits.iter().all(|hit| hit.score >= 0.3));
+
+        for id in ids {
+            TaskBmc::delete(ctx.clone(), mm.clone(), id).await?;
+        }
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_filter_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let mut ids = Vec::new();
+        for (story, status, tag) in [
+            ("Speed up the search endpoint", "open", "backend"),
+            ("Speed up the search page", "open", "frontend"),
+            ("Speed up the search index", "done", "backend"),
+        ] {
+            let task = TaskForCreate {
+                story: story.to_string(),
+                status: Some(status.to_string()),
+                tags: vec![tag.to_string()],
+            };
+            ids.push(TaskBmc::create(ctx.clone(), mm.clone(), task).await?);
+        }
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Open and tagged backend.
+        let filter = PointFilter::default()
+            .must(FieldCondition::Match(PayloadField::Status, "open".into()))
+            .must(FieldCondition::Match(PayloadField::Tags, "backend".into()));
+        let hits = TaskBmc::search(
+            ctx.clone(),
+            mm.clone(),
+            "speed up search",
+            &filter,
+            &SearchOptions::new(10),
+        )
+        .await?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].task.id, ids[0]);
+        assert_eq!(hits[0].task.tags, vec!["backend"]);
+
+        // -- Closing it drops it from the results once synced.
+        let task_u = TaskForUpdate {
+            id: ids[0],
+            story: "Speed up the search endpoint".to_string(),
+            status: Some("done".to_string()),
+            tags: None,
+        };
+        TaskBmc::update(ctx.clone(), mm.clone(), task_u).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+        let hits = TaskBmc::search(
+            ctx.clone(),
+            mm.clone(),
+            "speed up search",
+            &filter,
+            &SearchOptions::new(10),
+        )
+        .await?;
+        assert!(hits.is_empty());
+        assert_eq!(
+            TaskBmc::read(ctx.clone(), mm.clone(), ids[0]).await?.tags,
+            vec!["backend"]
+        );
+
+        for id in ids {
+            TaskBmc::delete(ctx.clone(), mm.clone(), id).await?;
+        }
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_long_story_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let filler = "The team reviewed the quarterly roadmap and the hiring plan. ".repeat(60);
+        let story =
+            format!("{filler}\nAction item: bake a chocolate cake for the launch.\n{filler}");
+        let task = TaskForCreate {
+            story: story.clone(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Several chunks, all carrying the task.
+        let points: Vec<_> = mm
+            .vs
+            .scroll_points(TaskBmc::COLLECTION_NAME)
+            .await?
+            .into_iter()
+            .filter(|(_, payload)| payload.task_id == id)
+            .collect();
+        assert!(points.len() > 2, "{} chunks", points.len());
+
+        // -- One hit for the task, the snippet is the matching chunk.
+        let hits = TaskBmc::search(
+            ctx.clone(),
+            mm.clone(),
+            "chocolate cake",
+            &PointFilter::default(),
+            &SearchOptions::new(10),
+        )
+        .await?;
+        assert_eq!(hits.iter().filter(|hit| hit.task.id == id).count(), 1);
+        let hit = hits.iter().find(|hit| hit.task.id == id).unwrap();
+        assert!(hit.snippet.contains("chocolate cake"), "{}", hit.snippet);
+        assert!(hit.snippet.len() < story.len());
+
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_other_owner_not_found() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let demo1 = UserBmc::first_by_username(ctx.clone(), mm.clone(), "demo1")
+            .await?
+            .unwrap();
+        let other_ctx = Ctx::new(demo1.id, demo1.username)?;
+        let task = TaskForCreate {
+            story: "This is a private story".to_string(),
+            ..Default::default()
+        };
+        let id = TaskBmc::create(ctx.clone(), mm.clone(), task).await?;
+        OutboxBmc::process_all(mm.clone()).await?;
+
+        // -- Read/update/delete by another user
+        let res = TaskBmc::read(other_ctx.clone(), mm.clone(), id).await;
+        assert!(matches!(res, Err(Error::EntityNotFound { .. })));
+        let task_u = TaskForUpdate {
+            id,
+            story: "Hijacked".to_string(),
+            ..Default::default()
+        };
+        let res = TaskBmc::update(other_ctx.clone(), mm.clone(), task_u).await;
+        assert!(matches!(res, Err(Error::EntityNotFound { .. })));
+        let res = TaskBmc::delete(other_ctx.clone(), mm.clone(), id).await;
+        assert!(matches!(res, Err(Error::EntityNotFound { .. })));
+
+        // -- Search by another user
+        let hits = TaskBmc::search(
+            other_ctx,
+            mm.clone(),
+            "private story",
+            &PointFilter::default(),
+            &SearchOptions::new(10),
+        )
+        .await?;
+        assert!(hits.iter().all(|hit| hit.task.id != id));
+
+        TaskBmc::delete(ctx, mm.clone(), id).await?;
+        OutboxBmc::process_all(mm).await?;
         Ok(())
     }
 }
@@ -0,0 +1,120 @@
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::crypt::hash_api_key;
+use crate::ctx::Ctx;
+use crate::model::error::{Error, Result};
+use crate::model::{ModelManager, VectorBackend};
+
+use super::embedder::Embedder;
+
+pub struct UserBmc;
+
+impl UserBmc {
+    const DB_TABLE_NAME: &'static str = "user";
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+}
+
+// endregion: --- User Types
+
+impl UserBmc {
+    pub async fn first_by_username(
+        _ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        username: &str,
+    ) -> Result<Option<User>> {
+        let user = sqlx::query_as(
+            r#"
+            SELECT id, username FROM "user" WHERE username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(&mm.db)
+        .await?;
+        Ok(user)
+    }
+
+    /// Resolve the user owning `api_key`. Only the hash of the key is ever compared.
+    pub async fn first_by_api_key(
+        _ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        api_key: &str,
+    ) -> Result<Option<User>> {
+        let user = sqlx::query_as(
+            r#"
+            SELECT id, username FROM "user" WHERE api_key_hash = $1
+            "#,
+        )
+        .bind(hash_api_key(api_key))
+        .fetch_optional(&mm.db)
+        .await?;
+        Ok(user)
+    }
+
+    pub async fn update_api_key(
+        _ctx: Ctx,
+        mm: ModelManager<impl Embedder, impl VectorBackend>,
+        id: i64,
+        api_key: &str,
+    ) -> Result<()> {
+        let count = sqlx::query(
+            r#"
+            UPDATE "user" SET api_key_hash = $1 WHERE id = $2
+            "#,
+        )
+        .bind(hash_api_key(api_key))
+        .bind(id)
+        .execute(&mm.db)
+        .await?
+        .rows_affected();
+        if count == 0 {
+            return Err(Error::EntityNotFound {
+                entity: Self::DB_TABLE_NAME,
+                id,
+            });
+        };
+        Ok(())
+    }
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::model::MemVecStore;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder};
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use serial_test::serial;
+
+    #[serial]
+    #[tokio::test]
+    async fn test_first_by_api_key_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let ctx = Ctx::root_ctx();
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let user = UserBmc::first_by_username(ctx.clone(), mm.clone(), "demo1")
+            .await?
+            .unwrap();
+
+        UserBmc::update_api_key(ctx.clone(), mm.clone(), user.id, "test_api_key").await?;
+        let found = UserBmc::first_by_api_key(ctx.clone(), mm.clone(), "test_api_key")
+            .await?
+            .unwrap();
+        assert_eq!(found.id, user.id);
+
+        let not_found = UserBmc::first_by_api_key(ctx.clone(), mm.clone(), "wrong_key").await?;
+        assert!(not_found.is_none());
+
+        // -- Restore the dev key for the other tests.
+        UserBmc::update_api_key(ctx, mm, user.id, _dev_utils::DEMO_API_KEY).await?;
+        Ok(())
+    }
+}
+// endregion: --- Test
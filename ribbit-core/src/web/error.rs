@@ -0,0 +1,304 @@
+use crate::web::mw_auth::CtxExtError;
+use crate::{ctx, model};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+use tracing::debug;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The payloads are only read by the error log of `mw_response_map`.
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum Error {
+    // -- CtxExtError
+    CtxExt(CtxExtError),
+
+    // -- Params
+    SearchParamsInvalid(String),
+
+    // -- Modules
+    Ctx(ctx::Error),
+    Model(model::Error),
+}
+
+// region:    --- Axum IntoResponse
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        debug!("{:<12} - web::Error {self:?}", "INTO_RES");
+
+        // Only the status is set here, the body is built by `mw_res_map`
+        // from the error stored in the response extensions.
+        let (status_code, _) = self.client_status_and_error();
+        let mut response = status_code.into_response();
+        response.extensions_mut().insert(Arc::new(self));
+
+        response
+    }
+}
+// endregion: --- Axum IntoResponse
+
+// region:    --- Error Boilerplate
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+impl std::error::Error for Error {}
+// endregion: --- Error Boilerplate
+
+// region:   --- error from
+impl From<ctx::Error> for Error {
+    fn from(err: ctx::Error) -> Self {
+        Self::Ctx(err)
+    }
+}
+
+impl From<model::Error> for Error {
+    fn from(err: model::Error) -> Self {
+        Self::Model(err)
+    }
+}
+// endregion: --- error from
+
+// region:    --- Client Error
+
+/// Errors as seen by the client. The codes are part of the API and must stay stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum ClientError {
+    NO_AUTH,
+    ENTITY_NOT_FOUND,
+    INVALID_FILTER,
+    INVALID_INPUT,
+    EMBEDDER_UNAVAILABLE,
+    VECTOR_STORE_UNAVAILABLE,
+    SERVICE_ERROR,
+}
+
+impl ClientError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NO_AUTH => "NO_AUTH",
+            Self::ENTITY_NOT_FOUND => "ENTITY_NOT_FOUND",
+            Self::INVALID_FILTER => "INVALID_FILTER",
+            Self::INVALID_INPUT => "INVALID_INPUT",
+            Self::EMBEDDER_UNAVAILABLE => "EMBEDDER_UNAVAILABLE",
+            Self::VECTOR_STORE_UNAVAILABLE => "VECTOR_STORE_UNAVAILABLE",
+            Self::SERVICE_ERROR => "SERVICE_ERROR",
+        }
+    }
+}
+
+impl Error {
+    pub fn client_status_and_error(&self) -> (StatusCode, ClientError) {
+        use model::embedder::Error as EmbedderError;
+        use model::store::Error as StoreError;
+
+        match self {
+            // -- Ctx
+            // The key could not be checked, it is not a bad key.
+            Self::CtxExt(CtxExtError::ModelAccessError(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ClientError::SERVICE_ERROR,
+            ),
+            Self::CtxExt(_) | Self::Ctx(_) => (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH),
+
+            // -- Params
+            Self::SearchParamsInvalid(_) => (StatusCode::BAD_REQUEST, ClientError::INVALID_INPUT),
+
+            // -- Model
+            Self::Model(model::Error::EntityNotFound { .. }) => {
+                (StatusCode::NOT_FOUND, ClientError::ENTITY_NOT_FOUND)
+            }
+            Self::Model(model::Error::TaskTooManyChunks { .. }) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, ClientError::INVALID_INPUT)
+            }
+            Self::Model(model::Error::Store(StoreError::InvalidFilter(_))) => {
+                (StatusCode::BAD_REQUEST, ClientError::INVALID_FILTER)
+            }
+            // The story can't be embedded as is. The other rejections (auth, quota, model)
+            // are ours, not the client's.
+            Self::Model(model::Error::Embedder(
+                EmbedderError::InputTooLong { .. }
+                | EmbedderError::OpenAIRequestRejected {
+                    status: 400 | 413 | 422,
+                    ..
+                },
+            )) => (StatusCode::UNPROCESSABLE_ENTITY, ClientError::INVALID_INPUT),
+            Self::Model(model::Error::Embedder(_)) => {
+                (StatusCode::BAD_GATEWAY, ClientError::EMBEDDER_UNAVAILABLE)
+            }
+            Self::Model(model::Error::Store(
+                StoreError::QdrantUrlNotFound(_)
+                | StoreError::QdrantFetchError(_)
+                | StoreError::QdrantUpdateError(_)
+                | StoreError::QdrantDeleteError(_)
+                | StoreError::QdrantCreateError(_)
+                | StoreError::PgVecFetchError(_)
+                | StoreError::PgVecUpdateError(_)
+                | StoreError::PgVecDeleteError(_)
+                | StoreError::PgVecCreateError(_),
+            )) => (
+                StatusCode::BAD_GATEWAY,
+                ClientError::VECTOR_STORE_UNAVAILABLE,
+            ),
+
+            // -- Fallback.
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ClientError::SERVICE_ERROR,
+            ),
+        }
+    }
+}
+// endregion: --- Client Error
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use crate::model::{embedder, store};
+
+    #[test]
+    fn test_client_status_and_error_ok() {
+        let cases = [
+            (
+                Error::Model(model::Error::EntityNotFound {
+                    entity: "task",
+                    id: 1,
+                }),
+                StatusCode::NOT_FOUND,
+                "ENTITY_NOT_FOUND",
+            ),
+            (
+                Error::Model(model::Error::Embedder(
+                    embedder::Error::OpenAIEmbedderRequestError("boom".to_string()),
+                )),
+                StatusCode::BAD_GATEWAY,
+                "EMBEDDER_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::Store(store::Error::QdrantFetchError(
+                    "boom".to_string(),
+                ))),
+                StatusCode::BAD_GATEWAY,
+                "VECTOR_STORE_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::Store(store::Error::PgVecFetchError(
+                    "boom".to_string(),
+                ))),
+                StatusCode::BAD_GATEWAY,
+                "VECTOR_STORE_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::Store(store::Error::PgVecUpdateError(
+                    "boom".to_string(),
+                ))),
+                StatusCode::BAD_GATEWAY,
+                "VECTOR_STORE_UNAVAILABLE",
+            ),
+            (
+                Error::SearchParamsInvalid("limit".to_string()),
+                StatusCode::BAD_REQUEST,
+                "INVALID_INPUT",
+            ),
+            (
+                Error::Model(model::Error::Store(store::Error::InvalidFilter(
+                    "status".to_string(),
+                ))),
+                StatusCode::BAD_REQUEST,
+                "INVALID_FILTER",
+            ),
+            (
+                Error::Model(model::Error::Embedder(embedder::Error::InputTooLong {
+                    tokens: 9000,
+                    max_tokens: 8000,
+                })),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "INVALID_INPUT",
+            ),
+            (
+                Error::Model(model::Error::Embedder(
+                    embedder::Error::OpenAIRequestRejected {
+                        status: 400,
+                        message: "bad input".to_string(),
+                    },
+                )),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "INVALID_INPUT",
+            ),
+            (
+                Error::Model(model::Error::Embedder(
+                    embedder::Error::OpenAIRequestRejected {
+                        status: 401,
+                        message: "bad key".to_string(),
+                    },
+                )),
+                StatusCode::BAD_GATEWAY,
+                "EMBEDDER_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::Embedder(
+                    embedder::Error::OpenAIRequestRejected {
+                        status: 429,
+                        message: "insufficient_quota".to_string(),
+                    },
+                )),
+                StatusCode::BAD_GATEWAY,
+                "EMBEDDER_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::Embedder(
+                    embedder::Error::OpenAIRequestRejected {
+                        status: 404,
+                        message: "model not found".to_string(),
+                    },
+                )),
+                StatusCode::BAD_GATEWAY,
+                "EMBEDDER_UNAVAILABLE",
+            ),
+            (
+                Error::Model(model::Error::TaskTooManyChunks {
+                    chunks: 70_000,
+                    max_chunks: 65_536,
+                }),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "INVALID_INPUT",
+            ),
+            (
+                Error::Model(model::Error::Sqlx(sqlx::Error::PoolTimedOut)),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "SERVICE_ERROR",
+            ),
+            (
+                Error::CtxExt(CtxExtError::TokenNotInHeader),
+                StatusCode::UNAUTHORIZED,
+                "NO_AUTH",
+            ),
+            (
+                Error::CtxExt(CtxExtError::UserNotFound),
+                StatusCode::UNAUTHORIZED,
+                "NO_AUTH",
+            ),
+            (
+                Error::CtxExt(CtxExtError::ModelAccessError("pool timed out".to_string())),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "SERVICE_ERROR",
+            ),
+            (
+                Error::Ctx(ctx::Error::CtxCannotNewRootCtx),
+                StatusCode::UNAUTHORIZED,
+                "NO_AUTH",
+            ),
+        ];
+        for (err, status, code) in cases {
+            let (s, c) = err.client_status_and_error();
+            assert_eq!(s, status, "status for {err:?}");
+            assert_eq!(c.as_str(), code, "code for {err:?}");
+        }
+    }
+}
+// endregion: --- Test
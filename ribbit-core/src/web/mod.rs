@@ -0,0 +1,10 @@
+// region:    --- Modules
+
+mod error;
+pub mod mw_auth;
+pub mod mw_res_map;
+pub mod routes_tasks;
+
+pub use self::error::{Error, Result};
+
+// endregion: --- Modules
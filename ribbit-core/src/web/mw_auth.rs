@@ -0,0 +1,92 @@
+use crate::ctx::Ctx;
+use crate::model::user::UserBmc;
+use crate::model::{Embedder, ModelManager, VectorBackend};
+use crate::web::{Error, Result};
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::{FromRequestParts, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::debug;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Resolve the `Ctx` from the bearer api key and store the result in the request extensions.
+///
+/// NOTE: It never rejects, the `Ctx` extractor does when the resolution failed.
+pub async fn mw_ctx_resolve<E: Embedder, V: VectorBackend>(
+    State(mm): State<ModelManager<E, V>>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response> {
+    debug!("{:<12} - mw_ctx_resolve", "MIDDLEWARE");
+
+    let result_ctx = ctx_resolve(mm, req.headers()).await;
+    if let Err(ref ex) = result_ctx {
+        debug!("{:<12} - mw_ctx_resolve - {ex:?}", "MIDDLEWARE");
+    }
+    req.extensions_mut().insert(result_ctx);
+
+    Ok(next.run(req).await)
+}
+
+async fn ctx_resolve<E: Embedder, V: VectorBackend>(
+    mm: ModelManager<E, V>,
+    headers: &HeaderMap,
+) -> CtxExtResult {
+    // -- Get the api key from the header.
+    let api_key = headers
+        .get(AUTHORIZATION)
+        .ok_or(CtxExtError::TokenNotInHeader)?
+        .to_str()
+        .map_err(|_| CtxExtError::TokenWrongFormat)?
+        .strip_prefix(BEARER_PREFIX)
+        .ok_or(CtxExtError::TokenWrongFormat)?;
+
+    // -- Get the user owning the key.
+    let user = UserBmc::first_by_api_key(Ctx::root_ctx(), mm, api_key)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?
+        .ok_or(CtxExtError::UserNotFound)?;
+
+    // -- Create the Ctx.
+    Ctx::new(user.id, user.username).map_err(|ex| CtxExtError::CtxCreateFail(ex.to_string()))
+}
+
+// region:    --- Ctx Extractor
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Ctx {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        debug!("{:<12} - Ctx", "EXTRACTOR");
+
+        parts
+            .extensions
+            .get::<CtxExtResult>()
+            .ok_or(Error::CtxExt(CtxExtError::CtxNotInRequestExt))?
+            .clone()
+            .map_err(Error::CtxExt)
+    }
+}
+// endregion: --- Ctx Extractor
+
+// region:    --- Ctx Extractor Result/Error
+type CtxExtResult = core::result::Result<Ctx, CtxExtError>;
+
+/// The payloads are only read by the error log of `mw_response_map`.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub enum CtxExtError {
+    TokenNotInHeader,
+    TokenWrongFormat,
+
+    UserNotFound,
+    ModelAccessError(String),
+    CtxNotInRequestExt,
+    CtxCreateFail(String),
+}
+// endregion: --- Ctx Extractor Result/Error
@@ -0,0 +1,84 @@
+use crate::web;
+use axum::http::{HeaderValue, Method, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+pub async fn mw_response_map(uri: Uri, req_method: Method, res: Response) -> Response {
+    debug!("{:<12} - mw_response_map", "RES_MAPPER");
+    let req_uuid = Uuid::new_v4();
+
+    // -- Get the eventual response error.
+    let web_error = res.extensions().get::<Arc<web::Error>>().map(Arc::as_ref);
+
+    // -- If web error, log it in full and build the client error response.
+    let mut res = match web_error {
+        Some(web_error) => {
+            error!(
+                "{:<12} - req_uuid: {req_uuid} - {req_method} {uri} - {web_error:?}",
+                "RES_MAPPER"
+            );
+            let (status_code, client_error) = web_error.client_status_and_error();
+            let client_error_body = json!({
+                "error": {
+                    "code": client_error.as_str(),
+                    "req_uuid": req_uuid.to_string(),
+                }
+            });
+            (status_code, Json(client_error_body)).into_response()
+        }
+        None => res,
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&req_uuid.to_string()) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    use crate::model;
+    use anyhow::Result;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn not_found() -> web::Result<()> {
+        Err(web::Error::Model(model::Error::EntityNotFound {
+            entity: "task",
+            id: 42,
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_mw_response_map_client_error_ok() -> Result<()> {
+        let app = Router::new()
+            .route("/not-found", get(not_found))
+            .layer(middleware::map_response(mw_response_map));
+
+        let res = app
+            .oneshot(Request::get("/not-found").body(Body::empty())?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let req_uuid = res.headers()[REQUEST_ID_HEADER].to_str()?.to_string();
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await?;
+        let body: Value = serde_json::from_slice(&bytes)?;
+        assert_eq!(body["error"]["code"], "ENTITY_NOT_FOUND");
+        assert_eq!(body["error"]["req_uuid"], req_uuid.as_str());
+        Ok(())
+    }
+}
+// endregion: --- Test
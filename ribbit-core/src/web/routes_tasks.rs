@@ -0,0 +1,321 @@
+use axum::extract::{Path, Query, State};
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+
+use crate::ctx::Ctx;
+use crate::model::store::{FieldCondition, PayloadField, PointFilter, SearchOptions};
+use crate::model::task::{Task, TaskBmc, TaskForCreate, TaskForUpdate, TaskHit};
+use crate::model::{Embedder, ModelManager, VectorBackend};
+use crate::web::mw_auth::mw_ctx_resolve;
+use crate::web::{Error, Result};
+use serde::Deserialize;
+
+const DEFAULT_SEARCH_LIMIT: u64 = 10;
+const MAX_SEARCH_LIMIT: u64 = 100;
+const MAX_SEARCH_OFFSET: u64 = 1_000;
+
+pub fn routes<E: Embedder, V: VectorBackend>(mm: ModelManager<E, V>) -> Router {
+    Router::new()
+        .route("/tasks", post(create_task::<E, V>))
+        .route("/tasks/search", get(search_tasks::<E, V>))
+        .route(
+            "/tasks/:id",
+            get(read_task::<E, V>)
+                .put(update_task::<E, V>)
+                .delete(delete_task::<E, V>),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            mm.clone(),
+            mw_ctx_resolve::<E, V>,
+        ))
+        .with_state(mm)
+}
+
+// region:   --- Handlers
+
+async fn create_task<E: Embedder, V: VectorBackend>(
+    ctx: Ctx,
+    State(mm): State<ModelManager<E, V>>,
+    Json(task_c): Json<TaskForCreate>,
+) -> Result<Json<Task>> {
+    let id = TaskBmc::create(ctx.clone(), mm.clone(), task_c).await?;
+    let task = TaskBmc::read(ctx, mm, id).await?;
+    Ok(Json(task))
+}
+
+async fn read_task<E: Embedder, V: VectorBackend>(
+    ctx: Ctx,
+    State(mm): State<ModelManager<E, V>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Task>> {
+    let task = TaskBmc::read(ctx, mm, id).await?;
+    Ok(Json(task))
+}
+
+async fn update_task<E: Embedder, V: VectorBackend>(
+    ctx: Ctx,
+    State(mm): State<ModelManager<E, V>>,
+    Path(id): Path<i64>,
+    Json(task_u): Json<TaskForUpdate>,
+) -> Result<Json<Task>> {
+    let task_u = TaskForUpdate { id, ..task_u };
+    TaskBmc::update(ctx.clone(), mm.clone(), task_u).await?;
+    let task = TaskBmc::read(ctx, mm, id).await?;
+    Ok(Json(task))
+}
+
+async fn delete_task<E: Embedder, V: VectorBackend>(
+    ctx: Ctx,
+    State(mm): State<ModelManager<E, V>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Task>> {
+    let task = TaskBmc::read(ctx.clone(), mm.clone(), id).await?;
+    TaskBmc::delete(ctx, mm, id).await?;
+    Ok(Json(task))
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<u64>,
+    offset: Option<u64>,
+    /// Hits scoring below are dropped.
+    min_score: Option<f32>,
+    status: Option<String>,
+    /// Comma separated, the task must have all of them.
+    tags: Option<String>,
+}
+
+impl SearchParams {
+    fn filter(&self) -> PointFilter {
+        let mut filter = PointFilter::default();
+        if let Some(status) = &self.status {
+            filter = filter.must(FieldCondition::Match(
+                PayloadField::Status,
+                status.as_str().into(),
+            ));
+        }
+        let tags = self.tags.iter().flat_map(|tags| tags.split(','));
+        for tag in tags.map(str::trim).filter(|tag| !tag.is_empty()) {
+            filter = filter.must(FieldCondition::Match(PayloadField::Tags, tag.into()));
+        }
+        filter
+    }
+
+    /// `SearchParamsInvalid` above `MAX_SEARCH_LIMIT` / `MAX_SEARCH_OFFSET`.
+    fn options(&self) -> Result<SearchOptions> {
+        let limit = self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if limit > MAX_SEARCH_LIMIT {
+            return Err(Error::SearchParamsInvalid(format!(
+                "limit {limit} above {MAX_SEARCH_LIMIT}"
+            )));
+        }
+        let offset = self.offset.unwrap_or(0);
+        if offset > MAX_SEARCH_OFFSET {
+            return Err(Error::SearchParamsInvalid(format!(
+                "offset {offset} above {MAX_SEARCH_OFFSET}"
+            )));
+        }
+        Ok(SearchOptions {
+            offset,
+            score_threshold: self.min_score,
+            ..SearchOptions::new(limit)
+        })
+    }
+}
+
+async fn search_tasks<E: Embedder, V: VectorBackend>(
+    ctx: Ctx,
+    State(mm): State<ModelManager<E, V>>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<TaskHit>>> {
+    let opts = params.options()?;
+    let hits = TaskBmc::search(ctx, mm, &params.q, &params.filter(), &opts).await?;
+    Ok(Json(hits))
+}
+
+// endregion: --- Handlers
+
+// region:   --- Test
+#[cfg(test)]
+mod tests {
+    use crate::model::outbox::OutboxBmc;
+    use crate::model::MemVecStore;
+    use crate::{_dev_utils, model::embedder::HashingEmbedder};
+
+    #[allow(unused)]
+    use super::*;
+    use anyhow::Result;
+    use axum::body::Body;
+    use axum::http::{header, Method, Request, StatusCode};
+    use axum::response::Response;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+    use tower::ServiceExt;
+
+    fn req(method: Method, uri: &str, body: Option<Value>) -> Result<Request<Body>> {
+        let builder = Request::builder().method(method).uri(uri).header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", _dev_utils::DEMO_API_KEY),
+        );
+        let req = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?,
+            None => builder.body(Body::empty())?,
+        };
+        Ok(req)
+    }
+
+    async fn body_json(res: Response) -> Result<Value> {
+        let bytes = hyper::body::to_bytes(res.into_body()).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_create_read_delete_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let app = Router::new().nest("/api", routes(mm.clone()));
+
+        // -- Create
+        let res = app
+            .clone()
+            .oneshot(req(
+                Method::POST,
+                "/api/tasks",
+                Some(json!({"story": "This is a story"})),
+            )?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        let created = body_json(res).await?;
+        let id = created["id"].as_i64().unwrap();
+        assert_eq!(created["story"], "This is a story");
+
+        // -- Read
+        let res = app
+            .clone()
+            .oneshot(req(Method::GET, &format!("/api/tasks/{id}"), None)?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_json(res).await?, created);
+
+        // -- Delete
+        let res = app
+            .clone()
+            .oneshot(req(Method::DELETE, &format!("/api/tasks/{id}"), None)?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // -- Read after delete
+        let res = app
+            .oneshot(req(Method::GET, &format!("/api/tasks/{id}"), None)?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_update_ok() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let app = Router::new().nest("/api", routes(mm.clone()));
+
+        let res = app
+            .clone()
+            .oneshot(req(
+                Method::POST,
+                "/api/tasks",
+                Some(json!({"story": "This is a story"})),
+            )?)
+            .await?;
+        let id = body_json(res).await?["id"].as_i64().unwrap();
+
+        let res = app
+            .clone()
+            .oneshot(req(
+                Method::PUT,
+                &format!("/api/tasks/{id}"),
+                Some(json!({"story": "This is a new story"})),
+            )?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_json(res).await?["story"], "This is a new story");
+
+        app.oneshot(req(Method::DELETE, &format!("/api/tasks/{id}"), None)?)
+            .await?;
+        OutboxBmc::process_all(mm).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_params_filter() -> Result<()> {
+        let params = SearchParams {
+            q: "search".to_string(),
+            limit: None,
+            offset: Some(10),
+            min_score: None,
+            status: Some("open".to_string()),
+            tags: Some("backend, api,".to_string()),
+        };
+        let filter = params.filter();
+        assert_eq!(filter.must.len(), 3);
+        assert_eq!(
+            filter.must[2],
+            FieldCondition::Match(PayloadField::Tags, "api".into())
+        );
+        let opts = params.options()?;
+        assert_eq!((opts.limit, opts.offset), (DEFAULT_SEARCH_LIMIT, 10));
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_search_err_limit_too_large() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let app = Router::new().nest("/api", routes(mm));
+
+        for query in [
+            "limit=18446744073709551615",
+            "limit=101",
+            "offset=18446744073709551615",
+        ] {
+            let uri = format!("/api/tasks/search?q=frog&{query}");
+            let res = app.clone().oneshot(req(Method::GET, &uri, None)?).await?;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{query}");
+        }
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_no_auth_err() -> Result<()> {
+        _dev_utils::init_dev().await;
+        let mm = ModelManager::<HashingEmbedder, MemVecStore>::from_config().await?;
+        let app = Router::new().nest("/api", routes(mm));
+
+        // -- No token
+        let res = app
+            .clone()
+            .oneshot(Request::get("/api/tasks/1000").body(Body::empty())?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // -- Unknown token
+        let res = app
+            .oneshot(
+                Request::get("/api/tasks/1000")
+                    .header(header::AUTHORIZATION, "Bearer not_a_key")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
+// endregion: --- Test